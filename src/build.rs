@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use snafu::{ensure, ResultExt};
+
+use super::error::*;
+use super::export::sh_quote;
+use super::script::{file_name, Script};
+use super::{Pier, PierResult};
+
+// Ends the scripts with a shebang which are written to a temporary file when the build runs.
+const HEREDOC_DELIMITER: &str = "PIER_SCRIPT_END";
+
+/// Writes a script to an executable file which runs without pier. The file runs the script the
+/// way pier run does, with its shebang or the default interpreter, its working directory and its
+/// steps, and passes its arguments on to the script.
+///
+/// Prerequisites are left to pier, so scripts which depend on other scripts can't be built.
+pub fn build_script(pier: &Pier, alias: &str, output: &Path, force: bool) -> PierResult<()> {
+    let script = pier.fetch_script(alias)?;
+    ensure!(
+        script.depends_on.as_ref().map_or(true, Vec::is_empty),
+        BuildDependencies { alias }
+    );
+    ensure!(force || !output.exists(), BuildOutputExists { path: output });
+
+    let content = standalone(pier, script)?;
+    fs::write(output, content).context(BuildWrite { path: output })?;
+    fs::set_permissions(output, fs::Permissions::from_mode(0o755))
+        .context(BuildWrite { path: output })?;
+
+    Ok(())
+}
+
+/// The content of the standalone file. Scripts with a shebang are already standalone unless they
+/// need another working directory, everything else becomes a sh script.
+fn standalone(pier: &Pier, script: &Script) -> PierResult<String> {
+    // Without an interpreter in the config pier runs commands with $SHELL, which may not exist on
+    // other machines, so the command itself goes into the sh script.
+    let interpreter = pier.config().default.interpreter.as_deref();
+    let command = match script.runs_reference() {
+        true => script.read_reference()?,
+        false => script.command.clone(),
+    };
+
+    let mut file = String::from("#!/bin/sh\n");
+    match script.description.as_deref().and_then(|text| text.lines().next()) {
+        Some(description) => file.push_str(&format!("# {}: {}\n", script.alias, description)),
+        None => file.push_str(&format!("# {}\n", script.alias)),
+    }
+    file.push_str("# Built by pier build, runs without pier.\n");
+
+    match &script.steps {
+        Some(steps) => {
+            if steps.iter().any(|step| step.has_shebang()) {
+                file.push_str(&temp_dir());
+            }
+            for (index, step) in steps.iter().enumerate() {
+                let name = format!("{}-{}", script.alias, index + 1);
+                file.push_str(&format!(
+                    "\n# Step {}/{}: {}\n(\n",
+                    index + 1,
+                    steps.len(),
+                    step.command.lines().next().unwrap_or_default()
+                ));
+                file.push_str(&setup(
+                    step.cwd.as_deref().or(script.cwd.as_deref()),
+                    step.env.as_ref(),
+                ));
+                file.push_str(&run(
+                    &name,
+                    &script.alias,
+                    &step.command,
+                    step.interpreter.as_deref().or(interpreter),
+                ));
+                // Like pier run, a failing step stops the script unless it allows errors.
+                match step.continue_on_error {
+                    true => file.push_str(") || :\n"),
+                    false => file.push_str(") || exit\n"),
+                }
+            }
+        }
+        None if command.starts_with("#!") && script.cwd.is_none() => return Ok(command),
+        None => {
+            if command.starts_with("#!") {
+                file.push_str(&temp_dir());
+            }
+            file.push_str(&setup(script.cwd.as_deref(), None));
+            file.push_str(&run(&script.alias, &script.alias, &command, interpreter));
+        }
+    }
+
+    Ok(file)
+}
+
+/// Creates the temporary directory for the parts with a shebang and removes it on exit.
+fn temp_dir() -> String {
+    String::from("dir=$(mktemp -d) || exit 1\ntrap 'rm -rf \"$dir\"' EXIT\n")
+}
+
+/// Changes to the working directory and exports the environment variables.
+fn setup(cwd: Option<&Path>, env: Option<&BTreeMap<String, String>>) -> String {
+    let mut lines = String::new();
+    if let Some(cwd) = cwd {
+        lines.push_str(&format!("cd {} || exit 1\n", sh_quote(&cwd.to_string_lossy())));
+    }
+    for (key, value) in env.into_iter().flatten() {
+        lines.push_str(&format!("{}={}; export {}\n", key, sh_quote(value), key));
+    }
+
+    lines
+}
+
+/// Runs a command with the arguments of the file. Commands with a shebang are written to a
+/// temporary file and executed, like pier run does, and other commands run with the interpreter
+/// or as part of the sh script when there is none.
+fn run(name: &str, alias: &str, command: &str, interpreter: Option<&[String]>) -> String {
+    if command.starts_with("#!") {
+        let path = format!("\"$dir\"/{}", sh_quote(file_name(name)));
+        return format!(
+            "cat > {path} <<'{delimiter}'\n{command}\n{delimiter}\n\
+             chmod 500 {path}\n{path} \"$@\"\n",
+            path = path,
+            delimiter = HEREDOC_DELIMITER,
+            command = command.trim_end_matches('\n'),
+        );
+    }
+
+    match interpreter {
+        Some(interpreter) => {
+            let words: Vec<String> = interpreter
+                .iter()
+                .map(String::as_str)
+                .chain(vec![command, alias])
+                .map(sh_quote)
+                .collect();
+            format!("{} \"$@\"\n", words.join(" "))
+        }
+        None => format!("{}\n", command.trim_end_matches('\n')),
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, ResultExt};
+
+use super::config::Scripts;
+use super::error::*;
+use super::import::reference_of;
+use super::script::Script;
+use super::usage::SortOrder;
+use super::{pier_err, Pier, PierResult};
+
+/// A collection of scripts to share with other configs, with the files the scripts reference.
+/// Bundles are TOML files which pier can also use as a config.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Bundle {
+    #[serde(default)]
+    pub scripts: Scripts,
+
+    // The referenced files by the alias of their script.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, BundleFile>,
+}
+
+/// A referenced file in a bundle.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BundleFile {
+    pub name: String,
+    pub content: String,
+}
+
+/// What happens to a script of a bundle with an alias that already exists.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Conflict {
+    Skip,
+    Overwrite,
+    Rename,
+}
+
+impl Conflict {
+    pub const VARIANTS: &'static [&'static str] = &["skip", "overwrite", "rename"];
+}
+
+impl FromStr for Conflict {
+    type Err = String;
+
+    fn from_str(conflict: &str) -> Result<Self, Self::Err> {
+        match conflict {
+            "skip" => Ok(Conflict::Skip),
+            "overwrite" => Ok(Conflict::Overwrite),
+            "rename" => Ok(Conflict::Rename),
+            _ => {
+                Err(format!(
+                    "Unknown conflict handling {}, expected one of {}",
+                    conflict,
+                    Conflict::VARIANTS.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+/// What importing a bundle does with one of its scripts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportAction {
+    Add,
+    Overwrite,
+    Skip,
+    // Adds the script under another alias.
+    Rename(String),
+    // The alias exists and there is no conflict handling to decide what to do.
+    Ask,
+}
+
+impl Bundle {
+    /// Bundles the scripts that match one of the tags. Scripts which run a file they reference
+    /// bring along its content.
+    pub fn export(pier: &Pier, tags: Option<Vec<String>>) -> PierResult<Self> {
+        let mut bundle = Bundle::default();
+        for script in pier.list_scripts(tags, SortOrder::Name)? {
+            // References to programs on $PATH aren't part of the scripts.
+            let bundles_file = script.runs_reference()
+                && script.reference.as_deref().map_or(false, |reference| reference.contains('/'));
+            if bundles_file {
+                let path = script.reference_path().unwrap_or_default();
+                let name = match path.file_name() {
+                    Some(name) => name.to_string_lossy().to_string(),
+                    None => script.alias.clone(),
+                };
+                bundle.files.insert(
+                    script.alias.clone(),
+                    BundleFile {
+                        name,
+                        content: script.read_reference()?,
+                    },
+                );
+            }
+            bundle.scripts.insert(script.alias.clone(), script.clone());
+        }
+
+        Ok(bundle)
+    }
+
+    /// Reads a bundle, which fails when one of its files would be written outside of the files
+    /// directory.
+    pub fn read(path: &Path) -> PierResult<Self> {
+        let content = fs::read_to_string(path).context(BundleRead { path })?;
+        let bundle: Bundle = toml::from_str(&content).context(BundleParse { path })?;
+        for (alias, file) in bundle.files.iter() {
+            check_file_path(alias, &file.name)?;
+        }
+
+        Ok(bundle)
+    }
+
+    pub fn to_toml(&self) -> PierResult<String> {
+        let content = toml::to_string_pretty(self).context(TomlSerialize)?;
+
+        Ok(content)
+    }
+
+    /// Decides what importing does with every script. Scripts with an alias that exists are
+    /// handled as the conflict says, or need to be asked about without one. Renamed scripts get
+    /// the first free alias with a number as suffix.
+    pub fn plan(&self, pier: &Pier, conflict: Option<Conflict>) -> Vec<(String, ImportAction)> {
+        let mut plan: Vec<(String, ImportAction)> = vec![];
+        for (alias, _) in self.scripts.iter() {
+            let action = match (pier.config().scripts.contains_key(alias), conflict) {
+                (false, _) => ImportAction::Add,
+                (true, Some(Conflict::Skip)) => ImportAction::Skip,
+                (true, Some(Conflict::Overwrite)) => ImportAction::Overwrite,
+                (true, Some(Conflict::Rename)) => {
+                    ImportAction::Rename(self.free_alias(pier, alias, &plan))
+                }
+                (true, None) => ImportAction::Ask,
+            };
+            plan.push((alias.clone(), action));
+        }
+
+        plan
+    }
+
+    /// The alias with the first number from 2 that neither the config nor the renamed scripts
+    /// use.
+    pub fn free_alias(
+        &self,
+        pier: &Pier,
+        alias: &str,
+        plan: &[(String, ImportAction)],
+    ) -> String {
+        (2..)
+            .map(|n| format!("{}-{}", alias, n))
+            .find(|candidate| {
+                !pier.config().scripts.contains_key(candidate)
+                    && !self.scripts.contains_key(candidate)
+                    && plan
+                        .iter()
+                        .all(|(_, action)| action != &ImportAction::Rename(candidate.clone()))
+            })
+            .expect("There is always a free alias")
+    }
+
+    /// The path the referenced file of a script is written to when it's imported as new alias.
+    pub fn file_path(
+        &self,
+        alias: &str,
+        new_alias: &str,
+        files_dir: &Path,
+    ) -> PierResult<Option<PathBuf>> {
+        match self.files.get(alias) {
+            Some(file) => {
+                check_file_path(new_alias, &file.name)?;
+                Ok(Some(files_dir.join(new_alias).join(&file.name)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Adds the scripts as planned and writes their referenced files to a directory for each
+    /// script in the files directory. The config still has to be written.
+    pub fn import(
+        &self,
+        pier: &mut Pier,
+        plan: &[(String, ImportAction)],
+        files_dir: &Path,
+    ) -> PierResult<usize> {
+        let mut imported = 0;
+        for (alias, action) in plan {
+            let new_alias = match action {
+                ImportAction::Add | ImportAction::Overwrite => alias,
+                ImportAction::Rename(new_alias) => new_alias,
+                ImportAction::Skip | ImportAction::Ask => continue,
+            };
+            let mut script: Script = match self.scripts.get(alias) {
+                Some(script) => script.clone(),
+                None => pier_err!(PierError::AliasNotFound {
+                    alias: alias.to_string()
+                }),
+            };
+            script.alias = new_alias.to_string();
+
+            let file = self.files.get(alias);
+            if let (Some(file), Some(path)) = (file, self.file_path(alias, new_alias, files_dir)?) {
+                if let Some(parent_dir) = path.parent() {
+                    fs::create_dir_all(parent_dir).context(CreateDirectory)?;
+                }
+                fs::write(&path, &file.content).context(BundleWrite { path: &path })?;
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+                    .context(BundleWrite { path: &path })?;
+                script.reference = Some(reference_of(&path));
+            }
+
+            pier.add_script(script, action == &ImportAction::Overwrite)?;
+            imported += 1;
+        }
+
+        Ok(imported)
+    }
+}
+
+/// Bundles come from others, so the alias and the name of a file may only add directories
+/// below the files directory. The name has to be a file name, every part of the alias has to be
+/// a name of a directory, which excludes absolute paths and "..".
+fn check_file_path(alias: &str, name: &str) -> PierResult<()> {
+    let is_name = |component: Component| matches!(component, Component::Normal(_));
+    let mut name_components = Path::new(name).components();
+    let name_is_file = name_components.next().map_or(false, is_name)
+        && name_components.next().is_none();
+    let path = format!("{}/{}", alias, name);
+    ensure!(
+        name_is_file && Path::new(alias).components().all(is_name) && !alias.is_empty(),
+        UnsafeBundlePath { path }
+    );
+
+    Ok(())
+}
+
+/// The directory for the referenced files of imported bundles.
+pub fn default_files_dir() -> PierResult<PathBuf> {
+    match dirs::data_dir() {
+        Some(path) => Ok(path.join("pier").join("files")),
+        None => pier_err!(PierError::NoDataDir),
+    }
+}
@@ -1,3 +1,8 @@
+use super::bundle::Conflict;
+use super::docs::DocsFormat;
+use super::import::Shell;
+use super::jobs::parse_signal;
+use super::usage::SortOrder;
 use std::path::PathBuf;
 use structopt::{clap::AppSettings, StructOpt};
 
@@ -6,7 +11,8 @@ pub enum CliSubcommand {
     /// Add a new script to config.
     Add {
         /// The command/script content to be executed.
-        /// If this argument is not found it will open your $EDITOR for you to enter the script into.
+        /// If this argument is not found it will open your $EDITOR for you to enter the script into,
+        /// unless the script references a file.
         command: Option<String>,
 
         /// The alias or name for the script.
@@ -21,6 +27,19 @@ pub enum CliSubcommand {
         #[structopt(short = "t", long = "tag")]
         tags: Option<Vec<String>>,
 
+        /// Aliases of the scripts that should run before this script.
+        #[structopt(short = "D", long = "depends-on")]
+        depends_on: Option<Vec<String>>,
+
+        /// An existing script file to run instead of a command, either a path or the name of an
+        /// executable on $PATH.
+        #[structopt(short = "r", long = "reference")]
+        reference: Option<String>,
+
+        /// Use the previous command of the shell history as the command.
+        #[structopt(long = "last", conflicts_with = "command")]
+        last: bool,
+
         /// Allows to overwrite the existing script
         #[structopt(short = "f", long = "force")]
         force: bool,
@@ -50,6 +69,15 @@ pub enum CliSubcommand {
         alias: String,
         /// The positional arguments to send to script.
         args: Vec<String>,
+        /// Run independent prerequisites of the script in parallel.
+        #[structopt(short = "p", long = "parallel")]
+        parallel: bool,
+        /// Capture the output of the run in a log file, see the logs subcommand.
+        #[structopt(short = "l", long = "log")]
+        log: bool,
+        /// Run the script in the background as a job, see the jobs subcommand.
+        #[structopt(short = "d", long = "detach")]
+        detach: bool,
     },
     /// alias: ls - List scripts
     ///
@@ -79,6 +107,17 @@ pub enum CliSubcommand {
         /// Filter based on tags.
         #[structopt(short = "t", long = "tag")]
         tags: Option<Vec<String>>,
+
+        /// The order of the scripts, based on the run history unless sorted by name.
+        #[structopt(short = "s", long = "sort", default_value = "frecency", possible_values = SortOrder::VARIANTS)]
+        sort: SortOrder,
+
+        /// Show the scripts as a tree of their groups.
+        #[structopt(short = "T", long = "tree")]
+        tree: bool,
+
+        /// Only list the scripts in this group, like docker/.
+        group: Option<String>,
     },
     /// alias: cp - Copy existing alias to the new one
     #[structopt(alias = "cp")]
@@ -99,6 +138,362 @@ pub enum CliSubcommand {
         #[structopt(short = "f", long = "force")]
         force: bool,
     },
+    /// alias: history - List the run history of scripts.
+    #[structopt(alias = "history")]
+    Runs {
+        /// Only display runs of the script matching alias.
+        alias: Option<String>,
+
+        /// Only display runs that failed.
+        #[structopt(long = "failed")]
+        failed: bool,
+
+        /// The max number of most recent runs to display.
+        #[structopt(short = "n", long = "limit")]
+        limit: Option<usize>,
+
+        /// Run the script of run number N again, with the same arguments, config file and directory.
+        #[structopt(long = "rerun")]
+        rerun: Option<usize>,
+    },
+    /// Show the captured output of logged runs of a script.
+    Logs {
+        /// The alias or name for the script.
+        alias: String,
+
+        /// Keep printing the output until the run has finished.
+        #[structopt(short = "f", long = "follow")]
+        follow: bool,
+
+        /// Show the log of run number N instead of the most recent run.
+        #[structopt(short = "r", long = "run")]
+        run: Option<usize>,
+    },
+    /// Run a script and run it again every time a watched file changes.
+    Watch {
+        /// The alias or name for the script.
+        alias: String,
+
+        /// The positional arguments to send to script.
+        args: Vec<String>,
+
+        /// A file or directory to watch, defaults to the current directory.
+        #[structopt(short = "p", long = "path")]
+        paths: Option<Vec<PathBuf>>,
+
+        /// Only run again when a file matching one of these globs changes.
+        #[structopt(short = "g", long = "glob")]
+        globs: Option<Vec<String>>,
+
+        /// Never run again when a file matching one of these globs changes.
+        #[structopt(short = "i", long = "ignore")]
+        ignore: Option<Vec<String>>,
+
+        /// Clear the screen before every run.
+        #[structopt(long = "clear")]
+        clear: bool,
+
+        /// How many milliseconds to wait for more changes before running the script.
+        #[structopt(short = "d", long = "debounce")]
+        debounce_ms: Option<u64>,
+
+        /// Also run again for changes to files ignored by .gitignore.
+        #[structopt(long = "no-gitignore")]
+        no_gitignore: bool,
+    },
+    /// Run the scripts with a schedule whenever they are due.
+    Daemon {
+        /// Only run the scripts that are due now, wait for them to finish and exit.
+        #[structopt(long = "once")]
+        once: bool,
+    },
+    /// Serve the scripts over a HTTP API to list, show and run them.
+    Serve {
+        /// The address to listen on.
+        #[structopt(short = "l", long = "listen", conflicts_with = "socket")]
+        listen: Option<String>,
+
+        /// Listen on a unix socket at this path instead of an address.
+        #[structopt(short = "s", long = "socket")]
+        socket: Option<PathBuf>,
+
+        /// The token clients send as a bearer token, required when listening on an address.
+        #[structopt(short = "t", long = "token", env = "PIER_SERVE_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+
+        /// An alias of a script that clients may run.
+        #[structopt(short = "a", long = "allow")]
+        allow: Vec<String>,
+    },
+    /// Answer line delimited JSON-RPC requests on stdin, for editors and other tools.
+    Rpc,
+    /// Add scripts from somewhere else, like a directory of executables.
+    Import {
+        #[structopt(subcommand)]
+        cmd: ImportSubcommand,
+    },
+    /// Put a small executable on $PATH for a script, which runs it with pier.
+    Install {
+        /// The alias or name for the script.
+        #[structopt(required_unless = "all")]
+        alias: Option<String>,
+
+        /// Install every script.
+        #[structopt(short = "a", long = "all", conflicts_with = "alias")]
+        all: bool,
+
+        /// The directory for the executables, defaults to ~/.local/bin.
+        #[structopt(short = "b", long = "bin-dir", parse(from_os_str))]
+        bin_dir: Option<PathBuf>,
+    },
+    /// Remove the executables that install put on $PATH.
+    Uninstall {
+        /// The alias or name for the script.
+        #[structopt(required_unless = "all")]
+        alias: Option<String>,
+
+        /// Uninstall every script of the config.
+        #[structopt(short = "a", long = "all", conflicts_with = "alias")]
+        all: bool,
+
+        /// Only remove the executables in this directory.
+        #[structopt(short = "b", long = "bin-dir", parse(from_os_str))]
+        bin_dir: Option<PathBuf>,
+    },
+    /// Write a script to an executable file which runs without pier.
+    Build {
+        /// The alias or name for the script.
+        alias: String,
+
+        /// The file to write, defaults to the alias in the current directory.
+        #[structopt(short = "o", long = "output", parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Replace the file if it exists.
+        #[structopt(short = "f", long = "force")]
+        force: bool,
+    },
+    /// Write a catalog of the scripts grouped by tag, in markdown or html.
+    Docs {
+        /// The format of the catalog: md or html. Defaults to the extension of the output file,
+        /// or md.
+        #[structopt(short = "F", long = "format")]
+        format: Option<DocsFormat>,
+
+        /// The file to write, the catalog is printed when not given.
+        #[structopt(short = "o", long = "output", parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Only include the scripts with one of these tags.
+        #[structopt(short = "t", long = "tag")]
+        tags: Option<Vec<String>>,
+    },
+    /// Write the scripts in another form, like functions for a shell.
+    Export {
+        #[structopt(subcommand)]
+        cmd: ExportSubcommand,
+    },
+    /// Manage the git repositories the config takes scripts from.
+    Sources {
+        #[structopt(subcommand)]
+        cmd: SourcesSubcommand,
+    },
+    /// Show the scripts with a schedule.
+    Schedule {
+        #[structopt(subcommand)]
+        cmd: ScheduleSubcommand,
+    },
+    /// List the scripts running in the background and the finished jobs.
+    Jobs,
+    /// Print the output of a background job until it has finished.
+    Attach {
+        /// The number of the job.
+        job: usize,
+    },
+    /// Send a signal to the processes of a background job.
+    Kill {
+        /// The number of the job.
+        job: usize,
+
+        /// The signal to send, by name or number.
+        #[structopt(short = "s", long = "signal", default_value = "TERM", parse(try_from_str = parse_signal))]
+        signal: i32,
+    },
+    /// Runs the script of a background job, used internally by run --detach.
+    #[structopt(setting = AppSettings::Hidden)]
+    Supervise {
+        /// The number of the job.
+        job: usize,
+    },
+    /// Show the most used and never used scripts.
+    Stats {
+        /// The max number of most used scripts to display.
+        #[structopt(short = "n", long = "limit", default_value = "10")]
+        limit: usize,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ScheduleSubcommand {
+    /// alias: ls - List the scheduled scripts with their next run.
+    #[structopt(alias = "ls")]
+    List,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum SourcesSubcommand {
+    /// Fetch the new commits of the sources and check out their refs.
+    Update,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ImportSubcommand {
+    /// Add a script for every executable in a directory, like ~/bin.
+    Dir {
+        /// The directory with the executables, subdirectories are included.
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+
+        /// Copy the content of the files into the config instead of referencing them.
+        #[structopt(long = "inline")]
+        inline: bool,
+
+        /// Add all scripts without asking for each one.
+        #[structopt(short = "y", long = "yes")]
+        yes: bool,
+
+        /// Replace existing scripts with the same alias.
+        #[structopt(short = "f", long = "force")]
+        force: bool,
+    },
+    /// Add a script for every target of a Makefile.
+    Make {
+        /// The Makefile, defaults to the one in the current directory.
+        #[structopt(parse(from_os_str))]
+        file: Option<PathBuf>,
+
+        /// Put this in front of the aliases, defaults to the name of the project directory. An
+        /// empty prefix keeps the task names as they are.
+        #[structopt(short = "p", long = "prefix")]
+        prefix: Option<String>,
+
+        /// Add all scripts without asking for each one.
+        #[structopt(short = "y", long = "yes")]
+        yes: bool,
+
+        /// Replace existing scripts with the same alias.
+        #[structopt(short = "f", long = "force")]
+        force: bool,
+    },
+    /// Add a script for every recipe of a justfile.
+    Just {
+        /// The justfile, defaults to the one in the current directory.
+        #[structopt(parse(from_os_str))]
+        file: Option<PathBuf>,
+
+        /// Put this in front of the aliases, defaults to the name of the project directory. An
+        /// empty prefix keeps the task names as they are.
+        #[structopt(short = "p", long = "prefix")]
+        prefix: Option<String>,
+
+        /// Add all scripts without asking for each one.
+        #[structopt(short = "y", long = "yes")]
+        yes: bool,
+
+        /// Replace existing scripts with the same alias.
+        #[structopt(short = "f", long = "force")]
+        force: bool,
+    },
+    /// Add a script for every script of a package.json.
+    Npm {
+        /// The package.json, defaults to the one in the current directory.
+        #[structopt(parse(from_os_str))]
+        file: Option<PathBuf>,
+
+        /// Put this in front of the aliases, defaults to the name of the project directory. An
+        /// empty prefix keeps the task names as they are.
+        #[structopt(short = "p", long = "prefix")]
+        prefix: Option<String>,
+
+        /// Add all scripts without asking for each one.
+        #[structopt(short = "y", long = "yes")]
+        yes: bool,
+
+        /// Replace existing scripts with the same alias.
+        #[structopt(short = "f", long = "force")]
+        force: bool,
+    },
+    /// Add scripts from the commands of the shell history that are long or often repeated.
+    History {
+        /// The shell of the history: bash, zsh or fish. Defaults to the shell in $SHELL.
+        #[structopt(short = "s", long = "shell")]
+        shell: Option<Shell>,
+
+        /// The history file, instead of the default file of the shell.
+        #[structopt(long = "file", parse(from_os_str))]
+        file: Option<PathBuf>,
+
+        /// The number of commands to suggest.
+        #[structopt(short = "n", long = "limit", default_value = "20")]
+        limit: usize,
+
+        /// Add all suggested scripts without asking for each one.
+        #[structopt(short = "y", long = "yes")]
+        yes: bool,
+
+        /// Replace existing scripts with the same alias.
+        #[structopt(short = "f", long = "force")]
+        force: bool,
+    },
+    /// Add the scripts of a bundle written by export bundle.
+    Bundle {
+        /// The bundle file.
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+
+        /// What to do with scripts whose alias already exists: skip, overwrite or rename. Asks
+        /// for each one when not given.
+        #[structopt(short = "c", long = "conflict", possible_values = Conflict::VARIANTS)]
+        conflict: Option<Conflict>,
+
+        /// Show what the import would change without changing anything.
+        #[structopt(short = "n", long = "dry-run")]
+        dry_run: bool,
+
+        /// The directory for the files the scripts reference, defaults to a pier directory in
+        /// the data directory of the user.
+        #[structopt(long = "files-dir", parse(from_os_str))]
+        files_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ExportSubcommand {
+    /// Print a file to source from the shell config with a function for every script, which
+    /// runs the script with pier run.
+    Shell {
+        /// The shell of the functions: bash, zsh or fish. Defaults to the shell in $SHELL.
+        #[structopt(short = "F", long = "format")]
+        format: Option<Shell>,
+
+        /// Only export the scripts with one of these tags.
+        #[structopt(short = "t", long = "tag")]
+        tags: Option<Vec<String>>,
+
+        /// Put plain shell commands in the functions, so they run without pier.
+        #[structopt(long = "inline")]
+        inline: bool,
+    },
+    /// Write scripts to a bundle to share them, see import bundle.
+    Bundle {
+        /// Only bundle the scripts with one of these tags.
+        #[structopt(short = "t", long = "tag")]
+        tags: Option<Vec<String>>,
+
+        /// The file to write, the bundle is printed when not given.
+        #[structopt(short = "o", long = "output", parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
 }
 
 #[derive(StructOpt, Debug)]
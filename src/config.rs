@@ -2,15 +2,15 @@ use std::{collections::BTreeMap, fmt, fs, path::PathBuf};
 use std::collections::btree_map::Iter;
 use std::marker::PhantomData;
 
-use serde::{Deserialize, Deserializer, Serialize};
-use serde::de::{MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, MapAccess, Visitor};
 use snafu::ResultExt;
 
 use super::error::*;
-use super::script::Script;
+use super::script::{Hooks, Script};
 use super::PierResult;
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct ConfigDefaultOpts {
     // Default interpreter to use if script doesn't have a shebang.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -19,20 +19,56 @@ pub struct ConfigDefaultOpts {
     // Default width of the command when listing the scripts.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub command_width: Option<usize>,
+
+    // Record every run in the run history, enabled unless set to false.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history: Option<bool>,
+
+    // Hooks which run around every script.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<Hooks>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Config {
     #[serde(default)]
     pub scripts: Scripts,
 
     #[serde(default)]
     pub default: ConfigDefaultOpts,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<Source>,
 }
 
-#[derive(Serialize, Debug, Default)]
+/// A git repository with a pier config whose scripts are part of this config, read-only.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Source {
+    pub git: String,
+
+    // The branch, tag or commit to use, the default branch when not set.
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+
+    // Put in front of the aliases of the scripts, like team/.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+
+    // The config file or directory in the repository, pier.toml when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Scripts(BTreeMap<String, Script>);
 
+// Separates the group from the rest of an alias, nested tables are joined with the first.
+pub const GROUP_SEPARATORS: &[char] = &['/', '.'];
+
+// What a script runs, a table with one of these is a script rather than a group. Groups can
+// hold scripts named like any other key of a script, such as watch or hooks.
+const SCRIPT_KEYS: &[&str] = &["command", "steps", "reference"];
+
 impl Config {
     /// Helper function to read file.
     pub fn read(path: &PathBuf) -> PierResult<String> {
@@ -64,12 +100,46 @@ impl Scripts {
         self.0.is_empty()
     }
 
+    /// The script with the alias, or the only script whose alias ends with it after a group.
     pub fn get(&self, key: &str) -> Option<&Script> {
-        self.0.get(key)
+        self.resolve(key).and_then(|alias| self.0.get(alias))
     }
 
+    /// Like get, but mutable.
     pub fn get_mut(&mut self, key: &str) -> Option<&mut Script> {
-        self.0.get_mut(key)
+        let alias = self.resolve(key)?.to_string();
+        self.0.get_mut(&alias)
+    }
+
+    /// The alias the key refers to: the key itself when a script has it, otherwise the alias
+    /// of the only script it is a suffix of, so docker/flush is found by flush.
+    pub fn resolve(&self, key: &str) -> Option<&str> {
+        if let Some((alias, _)) = self.0.get_key_value(key) {
+            return Some(alias);
+        }
+
+        match self.suffix_matches(key).as_slice() {
+            [alias] => Some(alias),
+            _ => None,
+        }
+    }
+
+    /// The aliases which are the key with other group separators, or which end with the key
+    /// after a group separator. Both separators are the same, so k8s.forward-mongo is found by
+    /// k8s/forward-mongo and by forward-mongo.
+    pub fn suffix_matches(&self, key: &str) -> Vec<&str> {
+        let key = normalize_separators(key);
+        self.0
+            .keys()
+            .filter(|alias| {
+                let alias = normalize_separators(alias);
+                alias == key
+                    || (alias.len() > key.len()
+                        && alias.ends_with(&key)
+                        && alias[..alias.len() - key.len()].ends_with('/'))
+            })
+            .map(String::as_str)
+            .collect()
     }
 
     pub fn contains_key(&self, key: &str) -> bool {
@@ -84,6 +154,10 @@ impl Scripts {
         self.0.remove(key)
     }
 
+    pub fn retain<F: FnMut(&String, &mut Script) -> bool>(&mut self, keep: F) {
+        self.0.retain(keep)
+    }
+
     pub fn iter(&self) -> Iter<'_, String, Script> {
         self.0.iter()
     }
@@ -116,14 +190,113 @@ impl<'de> Visitor<'de> for ScriptsVisitor
     {
         let mut map = Scripts(BTreeMap::new());
 
-        while let Some((key, mut value)) = access.next_entry::<String, Script>()? {
-            value.alias = key.clone();
-            map.0.insert(key, value);
+        while let Some((key, value)) = access.next_entry::<String, toml::Value>()? {
+            insert_entry(&mut map, key, value).map_err(de::Error::custom)?;
         }
 
         Ok(map)
     }
 }
+
+/// Adds the script of a table, or every script of a group with the name of the group in front
+/// of their aliases.
+fn insert_entry(
+    scripts: &mut Scripts,
+    alias: String,
+    value: toml::Value,
+) -> Result<(), toml::de::Error> {
+    let is_group = match &value {
+        toml::Value::Table(table) => {
+            !table.is_empty()
+                && table.values().all(toml::Value::is_table)
+                && !table.keys().any(|key| SCRIPT_KEYS.contains(&key.as_str()))
+        }
+        _ => false,
+    };
+
+    match (is_group, value) {
+        (true, toml::Value::Table(table)) => {
+            for (key, value) in table {
+                insert_entry(scripts, format!("{}/{}", alias, key), value)?;
+            }
+        }
+        (_, value) => {
+            let mut script: Script = value.try_into()?;
+            script.alias = alias.clone();
+            scripts.0.insert(alias, script);
+        }
+    }
+
+    Ok(())
+}
+
+/// A script or a group of them when writing the scripts as nested tables.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Node<'a> {
+    Script(&'a Script),
+    Group(BTreeMap<&'a str, Node<'a>>),
+}
+
+/// Puts the script into the group of every part of the alias before a slash. Aliases that can't
+/// be nested, as one of the groups is a script or a part is empty, stay as they are.
+fn insert_node<'a>(nodes: &mut BTreeMap<&'a str, Node<'a>>, alias: &'a str, script: &'a Script) {
+    let parts: Vec<&str> = alias.split('/').collect();
+    if parts.iter().any(|part| part.is_empty()) {
+        nodes.insert(alias, Node::Script(script));
+        return;
+    }
+
+    let (name, groups) = parts.split_last().expect("split always has a part");
+    let mut group = &mut *nodes;
+    for part in groups {
+        let node = group
+            .entry(part)
+            .or_insert_with(|| Node::Group(BTreeMap::new()));
+        group = match node {
+            Node::Group(children) => children,
+            Node::Script(_) => {
+                nodes.insert(alias, Node::Script(script));
+                return;
+            }
+        };
+    }
+    group.insert(name, Node::Script(script));
+}
+
+impl Serialize for Scripts {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+    {
+        let mut nodes = BTreeMap::new();
+        for (alias, script) in self.0.iter() {
+            insert_node(&mut nodes, alias, script);
+        }
+
+        nodes.serialize(serializer)
+    }
+}
+
+/// Whether the alias is in the group, like docker/flush in docker/ or docker. The group is
+/// matched as a whole, so docker doesn't contain dockerfile.
+pub fn in_group(alias: &str, group: &str) -> bool {
+    let alias = normalize_separators(alias);
+    let group = normalize_separators(group);
+    match group.ends_with('/') {
+        true => alias.starts_with(&group) && alias.len() > group.len(),
+        false => {
+            alias.starts_with(&group)
+                && alias[group.len()..].starts_with('/')
+                && alias.len() > group.len() + 1
+        }
+    }
+}
+
+/// The alias with every group separator written as a slash, as both separate groups.
+fn normalize_separators(alias: &str) -> String {
+    alias.replace(GROUP_SEPARATORS, "/")
+}
 // Custom serde deserialization
 impl<'de> Deserialize<'de> for Scripts
 {
@@ -1,8 +1,26 @@
-use super::{home, pier_err, xdg_config_home, PierError, PierResult};
-use dirs;
+use super::{home, pier_err, xdg_config_home, xdg_state_home, PierError, PierResult};
 use std::{env, path::PathBuf};
 pub const FALLBACK_COMMAND_DISPLAY_WIDTH: usize = 80;
-pub const FALLBACK_SHELL: &'static str = "/bin/sh";
+pub const FALLBACK_SHELL: &str = "/bin/sh";
+pub const HISTORY_FILE: &str = "history.toml";
+pub const LOGS_DIR: &str = "logs";
+pub const JOBS_DIR: &str = "jobs";
+pub const SCHEDULE_FILE: &str = "schedule.toml";
+pub const SHIMS_FILE: &str = "shims.toml";
+// The number of run logs kept for every script.
+pub const LOG_RETENTION: usize = 20;
+pub const LOG_FOLLOW_INTERVAL_MS: u64 = 200;
+pub const WATCH_DEBOUNCE_MS: u64 = 200;
+pub const WATCH_POLL_INTERVAL_MS: u64 = 100;
+pub const SERVE_DEFAULT_LISTEN: &str = "127.0.0.1:7878";
+pub const SCHEDULE_POLL_INTERVAL_MS: u64 = 1000;
+// Scheduled runs which are later than this are missed runs.
+pub const SCHEDULE_GRACE_SECONDS: i64 = 60;
+// The maximum number of missed runs of a script that are caught up on.
+pub const SCHEDULE_CATCH_UP_LIMIT: usize = 100;
+// The default options of a directory of scripts, hidden so it's never taken for a script.
+pub const DIRECTORY_DEFAULTS_FILE: &str = ".pier.toml";
+pub const SCRIPT_FILE_EXTENSION: &str = "toml";
 
 pub fn fallback_shell() -> Vec<String> {
     match env::var("SHELL") {
@@ -23,13 +41,19 @@ pub fn fallback_path() -> PierResult<PathBuf> {
 
     // Loops for a vector of possible paths and tries to generate config from the first
     // default path that exists.
-    for config_path in default_config_paths {
-        if let Some(path) = config_path {
-            if path.exists() {
-                return Ok(path);
-            }
+    for path in default_config_paths.into_iter().flatten() {
+        if path.exists() {
+            return Ok(path);
         }
     }
 
     pier_err!(PierError::NoConfigFile)
 }
+
+/// The directory where pier keeps its state such as the run history.
+pub fn state_dir() -> PierResult<PathBuf> {
+    match xdg_state_home!("pier") {
+        Some(path) => Ok(path),
+        None => pier_err!(PierError::NoStateDir),
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use snafu::OptionExt;
+
+use super::config::Scripts;
+use super::error::*;
+use super::PierResult;
+
+/// Resolves the prerequisites of a script into layers in topological order.
+///
+/// Every script appears only once even when several scripts share it as a dependency. The
+/// scripts inside one layer don't depend on each other so they can safely run in parallel, and
+/// the last layer only contains the requested alias.
+pub fn execution_layers(scripts: &Scripts, alias: &str) -> PierResult<Vec<Vec<String>>> {
+    let mut depths: BTreeMap<String, usize> = BTreeMap::new();
+    let mut path: Vec<String> = Vec::new();
+
+    visit(scripts, alias, &mut path, &mut depths)?;
+
+    let mut layers: Vec<Vec<String>> = Vec::new();
+    for (alias, depth) in depths {
+        if layers.len() <= depth {
+            layers.resize(depth + 1, Vec::new());
+        }
+        layers[depth].push(alias);
+    }
+
+    Ok(layers)
+}
+
+/// Depth first search which returns how many levels of prerequisites a script has.
+fn visit(
+    scripts: &Scripts,
+    alias: &str,
+    path: &mut Vec<String>,
+    depths: &mut BTreeMap<String, usize>,
+) -> PierResult<usize> {
+    if let Some(depth) = depths.get(alias) {
+        return Ok(*depth);
+    }
+
+    if let Some(start) = path.iter().position(|visited| visited == alias) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(alias.to_string());
+        return DependencyCycle { path: cycle }.fail();
+    }
+
+    let script = scripts.get(alias).context(AliasNotFound {
+        alias: &alias.to_string(),
+    })?;
+
+    path.push(alias.to_string());
+
+    let mut depth = 0;
+    if let Some(depends_on) = &script.depends_on {
+        let unique: BTreeSet<&String> = depends_on.iter().collect();
+        for dependency in unique {
+            depth = depth.max(visit(scripts, dependency, path, depths)? + 1);
+        }
+    }
+
+    path.pop();
+    depths.insert(alias.to_string(), depth);
+
+    Ok(depth)
+}
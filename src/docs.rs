@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::script::{shebang_interpreter, Script};
+use super::usage::SortOrder;
+use super::{Pier, PierResult};
+
+/// The format of the script catalog.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocsFormat {
+    Markdown,
+    Html,
+}
+
+impl FromStr for DocsFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "md" | "markdown" => Ok(DocsFormat::Markdown),
+            "html" => Ok(DocsFormat::Html),
+            _ => Err(format!("Unknown format {}, use md or html", format)),
+        }
+    }
+}
+
+impl DocsFormat {
+    /// The format that goes with the extension of the file, markdown unless it's html.
+    pub fn of_file(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("html") | Some("htm") => DocsFormat::Html,
+            _ => DocsFormat::Markdown,
+        }
+    }
+}
+
+/// A part of the catalog, rendered in either format.
+enum Block {
+    Heading(usize, String),
+    Text(String),
+    // A label with a list of values which are shown as code.
+    Field(&'static str, Vec<String>),
+    Code(String, String),
+}
+
+/// Renders a catalog of the scripts that match one of the tags, grouped by tag. Every script
+/// shows its description, reference, working directory and prerequisites, and its command or
+/// steps as code in the language of the shebang or the interpreter.
+///
+/// Scripts with more than one tag are in every group, scripts without tags come last.
+pub fn render_docs(
+    pier: &Pier,
+    format: DocsFormat,
+    tags: Option<Vec<String>>,
+) -> PierResult<String> {
+    let mut groups: BTreeMap<&str, Vec<&Script>> = BTreeMap::new();
+    let mut untagged = vec![];
+    for script in pier.list_scripts(tags.clone(), SortOrder::Name)? {
+        let script_tags = script.tags.iter().flatten().filter(|tag| {
+            tags.as_ref().map_or(true, |tags| tags.contains(tag))
+        });
+        let mut tagged = false;
+        for tag in script_tags {
+            groups.entry(tag).or_default().push(script);
+            tagged = true;
+        }
+        if !tagged {
+            untagged.push(script);
+        }
+    }
+
+    let interpreter = pier.config().default.interpreter.as_ref();
+    let language = interpreter
+        .and_then(|interpreter| interpreter.first())
+        .map(|program| language_of(program))
+        .unwrap_or_else(|| "sh".to_string());
+
+    let mut blocks = vec![Block::Heading(1, "Scripts".to_string())];
+    // Without any tags there is nothing to group by, so the scripts are right under the title.
+    let grouped = !groups.is_empty();
+    let script_level = match grouped {
+        true => 3,
+        false => 2,
+    };
+    for (group, scripts) in groups {
+        blocks.push(Block::Heading(2, group.to_string()));
+        for script in scripts {
+            script_blocks(script, script_level, &language, &mut blocks);
+        }
+    }
+    if !untagged.is_empty() {
+        if grouped {
+            blocks.push(Block::Heading(2, "Untagged".to_string()));
+        }
+        for script in untagged {
+            script_blocks(script, script_level, &language, &mut blocks);
+        }
+    }
+
+    Ok(match format {
+        DocsFormat::Markdown => markdown(&blocks),
+        DocsFormat::Html => html(&blocks),
+    })
+}
+
+fn script_blocks(script: &Script, level: usize, language: &str, blocks: &mut Vec<Block>) {
+    blocks.push(Block::Heading(level, script.alias.clone()));
+    if let Some(description) = &script.description {
+        blocks.push(Block::Text(description.clone()));
+    }
+    if let Some(reference) = &script.reference {
+        blocks.push(Block::Field("Reference", vec![reference.clone()]));
+    }
+    if let Some(cwd) = &script.cwd {
+        blocks.push(Block::Field("Runs in", vec![cwd.display().to_string()]));
+    }
+    if let Some(depends_on) = script.depends_on.as_ref().filter(|aliases| !aliases.is_empty()) {
+        blocks.push(Block::Field("Depends on", depends_on.clone()));
+    }
+
+    match &script.steps {
+        Some(steps) => {
+            for (index, step) in steps.iter().enumerate() {
+                blocks.push(Block::Text(format!("Step {}:", index + 1)));
+                let step_language = match &step.interpreter {
+                    Some(interpreter) => interpreter.first().map(|program| language_of(program)),
+                    None => None,
+                };
+                blocks.push(code(&step.command, step_language.as_deref().unwrap_or(language)));
+            }
+        }
+        // Scripts which run their reference show the content of the file when it can be read.
+        None if script.runs_reference() => {
+            if let Ok(content) = script.read_reference() {
+                blocks.push(code(&content, language));
+            }
+        }
+        None => blocks.push(code(&script.command, language)),
+    }
+}
+
+/// A code block in the language of the shebang, or the language of the interpreter without one.
+fn code(command: &str, language: &str) -> Block {
+    let language = match shebang_interpreter(command) {
+        Some(program) => language_of(program),
+        None => language.to_string(),
+    };
+
+    Block::Code(language, command.trim_end().to_string())
+}
+
+/// The name highlighters know the language of the program by, without the version.
+fn language_of(program: &str) -> String {
+    let name = program.rsplit('/').next().unwrap_or(program);
+    match name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+        "node" | "nodejs" => "javascript".to_string(),
+        "" => "sh".to_string(),
+        language => language.to_string(),
+    }
+}
+
+fn markdown(blocks: &[Block]) -> String {
+    let mut document: Vec<String> = vec![];
+    for block in blocks {
+        document.push(match block {
+            Block::Heading(level, text) => format!("{} {}", "#".repeat(*level), text),
+            Block::Text(text) => text.clone(),
+            Block::Field(label, values) => {
+                let values: Vec<String> =
+                    values.iter().map(|value| format!("`{}`", value)).collect();
+                format!("{}: {}", label, values.join(", "))
+            }
+            Block::Code(language, content) => {
+                // The fence is longer than any run of backticks in the content.
+                let longest = content
+                    .split(|c| c != '`')
+                    .map(str::len)
+                    .max()
+                    .unwrap_or_default();
+                let fence = "`".repeat(longest.max(2) + 1);
+                format!("{}{}\n{}\n{}", fence, language, content, fence)
+            }
+        });
+    }
+
+    document.join("\n\n") + "\n"
+}
+
+fn html(blocks: &[Block]) -> String {
+    let mut document = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Scripts</title>\n</head>\n<body>\n",
+    );
+    for block in blocks {
+        document.push_str(&match block {
+            Block::Heading(level, text) => format!("<h{0}>{1}</h{0}>\n", level, escape(text)),
+            Block::Text(text) => format!("<p>{}</p>\n", escape(text)),
+            Block::Field(label, values) => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|value| format!("<code>{}</code>", escape(value)))
+                    .collect();
+                format!("<p>{}: {}</p>\n", label, values.join(", "))
+            }
+            Block::Code(language, content) => format!(
+                "<pre><code class=\"language-{}\">{}</code></pre>\n",
+                escape(language),
+                escape(content)
+            ),
+        });
+    }
+    document.push_str("</body>\n</html>\n");
+
+    document
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -43,6 +43,9 @@ pub enum PierError {
     #[snafu(display("error: Command execution failed with: {}", source))]
     CommandExec { source: std::io::Error },
 
+    #[snafu(display("error: EmptyInterpreter: The interpreter of {} has no program to run", alias))]
+    EmptyInterpreter { alias: String },
+
     #[snafu(display("error: No default config file found. See help for more info."))]
     NoConfigFile,
 
@@ -60,4 +63,259 @@ pub enum PierError {
 
     #[snafu(display("error: Failed to create directory. {}", source))]
     CreateDirectory { source: std::io::Error },
+
+    #[snafu(display("error: DependencyCycle: {}", path.join(" -> ")))]
+    DependencyCycle { path: Vec<String> },
+
+    #[snafu(display("error: No state directory found, set $XDG_STATE_HOME or $HOME."))]
+    NoStateDir,
+
+    #[snafu(display("error: Unable to read run history from {}: {}", path.display(), source))]
+    HistoryRead {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to parse run history from {}: {}", path.display(), source))]
+    HistoryParse {
+        source: toml::de::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to write run history to {}: {}", path.display(), source))]
+    HistoryWrite {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: RunNotFound: No run found with number {}", id))]
+    RunNotFound { id: usize },
+
+    #[snafu(display("error: Unable to write log to {}: {}", path.display(), source))]
+    LogWrite {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to read log from {}: {}", path.display(), source))]
+    LogRead {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: LogNotFound: No logs found for {}", alias))]
+    LogNotFound { alias: String },
+
+    #[snafu(display("error: JobNotFound: No job found with number {}", id))]
+    JobNotFound { id: usize },
+
+    #[snafu(display("error: JobNotRunning: Job {} is not running", id))]
+    JobNotRunning { id: usize },
+
+    #[snafu(display("error: Unable to read job from {}: {}", path.display(), source))]
+    JobRead {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to parse job from {}: {}", path.display(), source))]
+    JobParse {
+        source: toml::de::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to write job to {}: {}", path.display(), source))]
+    JobWrite {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to signal job {}: {}", id, source))]
+    JobSignal { source: std::io::Error, id: usize },
+
+    #[snafu(display("error: Invalid schedule {}: {}", expression, source))]
+    InvalidSchedule {
+        source: cron::error::Error,
+        expression: String,
+    },
+
+    #[snafu(display("error: Unable to read schedule state from {}: {}", path.display(), source))]
+    ScheduleRead {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to parse schedule state from {}: {}", path.display(), source))]
+    ScheduleParse {
+        source: toml::de::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to write schedule state to {}: {}", path.display(), source))]
+    ScheduleWrite {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to listen on {}: {}", address, source))]
+    ServeListen {
+        source: std::io::Error,
+        address: String,
+    },
+
+    #[snafu(display("error: ServeTokenMissing: A token is required to listen on an address, use --token or PIER_SERVE_TOKEN"))]
+    ServeTokenMissing,
+
+    #[snafu(display("error: Unable to serialize to JSON: {}", source))]
+    JsonSerialize { source: serde_json::Error },
+
+    #[snafu(display("error: Unable to watch files for changes: {}", source))]
+    WatchFiles { source: notify::Error },
+
+    #[snafu(display("error: Invalid glob {}: {}", glob, source))]
+    InvalidGlob {
+        source: globset::Error,
+        glob: String,
+    },
+
+    #[snafu(display("error: Unable to read .gitignore: {}", source))]
+    GitignoreParse { source: ignore::Error },
+
+    #[snafu(display("error: Unable to change directory to {}: {}", path.display(), source))]
+    ChangeDirectory {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: InvalidScriptFileName: The alias {} can't be used as a file name", alias))]
+    InvalidScriptFileName { alias: String },
+
+    #[snafu(display("error: DuplicateScriptFile: Both {} and {} are files for the script {}", first.display(), second.display(), alias))]
+    DuplicateScriptFile {
+        alias: String,
+        first: PathBuf,
+        second: PathBuf,
+    },
+
+    #[snafu(display("error: ReferenceNotFound: The file {} of the script {} doesn't exist", reference, alias))]
+    ReferenceNotFound { alias: String, reference: String },
+
+    #[snafu(display("error: Unable to read the referenced file {}: {}", path.display(), source))]
+    ReferenceRead {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to read {} for import: {}", path.display(), source))]
+    ImportRead {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: UnsupportedShell: Unable to import the history of {}, use --shell with bash, zsh or fish", shell))]
+    UnsupportedShell { shell: String },
+
+    #[snafu(display("error: HistoryEmpty: No commands found in the shell history"))]
+    HistoryEmpty,
+
+    #[snafu(display("error: Unable to parse {} for import: {}", path.display(), source))]
+    ImportJson {
+        source: serde_json::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to read the installed shims from {}: {}", path.display(), source))]
+    ShimsRead {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to parse the installed shims from {}: {}", path.display(), source))]
+    ShimsParse {
+        source: toml::de::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to write shim {}: {}", path.display(), source))]
+    ShimsWrite {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: ShimShadowsBinary: Not installing {}, it would shadow {}", alias, path.display()))]
+    ShimShadowsBinary { alias: String, path: PathBuf },
+
+    #[snafu(display("error: ShimNameTaken: Not installing {}, the shim {} is for {}", alias, path.display(), other))]
+    ShimNameTaken {
+        alias: String,
+        other: String,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: No bin directory found, use --bin-dir or set $HOME."))]
+    NoBinDir,
+
+    #[snafu(display("error: BuildDependencies: The script {} depends on other scripts, which only pier can run", alias))]
+    BuildDependencies { alias: String },
+
+    #[snafu(display("error: BuildOutputExists: {} already exists, use --force to replace it", path.display()))]
+    BuildOutputExists { path: PathBuf },
+
+    #[snafu(display("error: Unable to write the built script to {}: {}", path.display(), source))]
+    BuildWrite {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to write the docs to {}: {}", path.display(), source))]
+    DocsWrite {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to read the bundle {}: {}", path.display(), source))]
+    BundleRead {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to parse the bundle {}: {}", path.display(), source))]
+    BundleParse {
+        source: toml::de::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: Unable to write {} from the bundle: {}", path.display(), source))]
+    BundleWrite {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("error: UnsafeBundlePath: The bundle would write {} outside of the files directory", path))]
+    UnsafeBundlePath { path: String },
+
+    #[snafu(display("error: No data directory found for the files of the bundle, use --files-dir."))]
+    NoDataDir,
+
+    #[snafu(display("error: No cache directory found for the sources, set $XDG_CACHE_HOME or $HOME."))]
+    NoCacheDir,
+
+    #[snafu(display("error: Unable to run git for the sources: {}", source))]
+    SourceGitExec { source: std::io::Error },
+
+    #[snafu(display("error: SourceGitFailed: git {} failed for the source {}: {}", command, git, stderr))]
+    SourceGitFailed {
+        git: String,
+        command: String,
+        stderr: String,
+    },
+
+    #[snafu(display("error: ReadOnlyScript: The script {} comes from the source {}, change it in there", alias, git))]
+    ReadOnlyScript { alias: String, git: String },
+
+    #[snafu(display("error: AmbiguousAlias: {} could be any of {}", alias, matches.join(", ")))]
+    AmbiguousAlias { alias: String, matches: Vec<String> },
+
+    #[snafu(display("error: GroupNotFound: No scripts found in group {}", group))]
+    GroupNotFound { group: String },
 }
@@ -0,0 +1,65 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Something that happened while pier was working, for the program using pier to report. The
+/// library never prints, the pier binary prints these.
+#[derive(Debug, Clone)]
+pub enum Event {
+    // A script starts running, after its prerequisites.
+    ScriptStarted { alias: String },
+    // A step of a multi-step script starts, counted from 1.
+    StepStarted {
+        alias: String,
+        step: usize,
+        steps: usize,
+        command: String,
+    },
+    ScriptFinished { alias: String },
+    // The run history couldn't be read or written, pier went on without it.
+    HistoryFailed { error: String },
+
+    // The HTTP API server accepts requests at the address.
+    Listening { address: String },
+    // The HTTP API server answered a request with the status, or failed to.
+    Request {
+        method: String,
+        url: String,
+        status: Result<u16, String>,
+    },
+
+    // The scheduler picked up changes to the config file.
+    ConfigReloaded { path: PathBuf },
+    // The changed config file couldn't be loaded, the scheduler keeps the previous one.
+    ConfigReloadFailed { error: String },
+    // The runs of a script that were missed while the scheduler wasn't running are skipped.
+    MissedRunsSkipped { alias: String },
+    ScheduledRunStarted { alias: String },
+    // A scheduled run finished with the exit code, none when a signal ended it.
+    ScheduledRunFinished { alias: String, exit_code: Option<i32> },
+    // The scheduler lost track of a run.
+    ScheduledRunFailed { alias: String, error: String },
+
+    // A source couldn't be cloned or read, its scripts are left out.
+    SourceSkipped { git: String, error: String },
+}
+
+/// Receives the events of a Pier, which can come from more than one thread.
+#[derive(Clone)]
+pub(crate) struct EventHandler(Arc<dyn Fn(&Event) + Send + Sync>);
+
+impl EventHandler {
+    pub(crate) fn new<F: Fn(&Event) + Send + Sync + 'static>(handler: F) -> Self {
+        EventHandler(Arc::new(handler))
+    }
+
+    pub(crate) fn emit(&self, event: Event) {
+        (self.0)(&event)
+    }
+}
+
+impl fmt::Debug for EventHandler {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("EventHandler")
+    }
+}
@@ -0,0 +1,126 @@
+use std::fs;
+
+use super::import::Shell;
+use super::script::Script;
+use super::usage::SortOrder;
+use super::{Pier, PierResult};
+
+/// Creates a file for the shell to source, with a function for every script that matches one of
+/// the tags. The functions call pier run with the config of pier, or hold the command itself
+/// when inline is set and the script is a plain shell command.
+///
+/// Scripts with an alias the shell doesn't accept as a function name are left out with a comment.
+pub fn shell_functions(
+    pier: &Pier,
+    shell: Shell,
+    tags: Option<Vec<String>>,
+    inline: bool,
+) -> PierResult<String> {
+    let scripts = pier.list_scripts(tags, SortOrder::Name)?;
+    let mut runner = "pier".to_string();
+    if !pier.path().as_os_str().is_empty() {
+        let path = fs::canonicalize(pier.path()).unwrap_or_else(|_| pier.path().to_path_buf());
+        let path = path.to_string_lossy();
+        runner = format!("{} --config-file {}", runner, quote(shell, &path));
+    }
+
+    let mut file = format!(
+        "# Functions for the scripts of pier, source this file from your {} config.\n",
+        shell_name(shell)
+    );
+    for script in scripts {
+        file.push('\n');
+        if !is_function_name(&script.alias) {
+            file.push_str(&format!(
+                "# Skipped {}, the alias is not a valid function name.\n",
+                script.alias
+            ));
+            continue;
+        }
+        if let Some(description) = &script.description {
+            for line in description.lines() {
+                file.push_str(format!("# {}", line).trim_end());
+                file.push('\n');
+            }
+        }
+
+        let inlined = inline && runs_inline(script);
+        let body = match inlined {
+            true => inline_body(shell, &script.command),
+            false => {
+                let args = match shell {
+                    Shell::Fish => "$argv",
+                    Shell::Bash | Shell::Zsh => "\"$@\"",
+                };
+                format!("    {} run {} -- {}\n", runner, script.alias, args)
+            }
+        };
+        match shell {
+            Shell::Fish => file.push_str(&format!("function {}\n{}end\n", script.alias, body)),
+            // Inlined commands run in a subshell, so exit, cd or set don't change the shell of
+            // the user.
+            Shell::Bash | Shell::Zsh if inlined => {
+                file.push_str(&format!("{}() (\n{})\n", script.alias, body))
+            }
+            Shell::Bash | Shell::Zsh => {
+                file.push_str(&format!("{}() {{\n{}}}\n", script.alias, body))
+            }
+        }
+    }
+
+    Ok(file)
+}
+
+fn shell_name(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => "bash",
+        Shell::Zsh => "zsh",
+        Shell::Fish => "fish",
+    }
+}
+
+/// Whether the script is only a shell command, which can go into the function without pier.
+fn runs_inline(script: &Script) -> bool {
+    !script.command.is_empty()
+        && !script.has_shebang()
+        && script.steps.is_none()
+        && script.depends_on.is_none()
+        && script.hooks.is_none()
+        && script.cwd.is_none()
+}
+
+/// The command as the body of a function. Fish doesn't understand the sh syntax of the commands,
+/// so fish passes the command and the arguments on to sh.
+fn inline_body(shell: Shell, command: &str) -> String {
+    match shell {
+        Shell::Fish => format!("    sh -c {} sh $argv\n", quote(shell, command)),
+        Shell::Bash | Shell::Zsh => command
+            .lines()
+            .map(|line| match line.is_empty() {
+                true => "\n".to_string(),
+                false => format!("    {}\n", line),
+            })
+            .collect(),
+    }
+}
+
+fn is_function_name(alias: &str) -> bool {
+    !alias.is_empty()
+        && !alias.starts_with('-')
+        && alias
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-.:+@".contains(c))
+}
+
+/// Quotes the text with single quotes, which fish escapes differently than the other shells.
+pub(crate) fn quote(shell: Shell, text: &str) -> String {
+    match shell {
+        Shell::Fish => format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'")),
+        Shell::Bash | Shell::Zsh => sh_quote(text),
+    }
+}
+
+/// Quotes the text with single quotes for sh and the shells like it.
+pub(crate) fn sh_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
@@ -0,0 +1,148 @@
+use std::fs::{self, OpenOptions};
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::process::Output;
+use std::time::Instant;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, OptionExt, ResultExt};
+
+use super::defaults::*;
+use super::error::*;
+use super::{Pier, PierResult, RunOptions, RunOutcome};
+
+/// A single invocation of a script.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunRecord {
+    // The number of the run, counted from the oldest run in the history.
+    #[serde(skip)]
+    pub id: usize,
+    pub timestamp: DateTime<Local>,
+    pub alias: String,
+    pub args: Vec<String>,
+    pub config: PathBuf,
+    pub cwd: PathBuf,
+    // Missing when the script was terminated by a signal.
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    // The log file with the output of the run, if it was logged.
+    pub log: Option<PathBuf>,
+}
+
+impl RunRecord {
+    /// The log file of the run, which has to be a run of the given alias.
+    pub fn log_of(&self, alias: &str) -> PierResult<PathBuf> {
+        ensure!(self.alias == alias, RunNotFound { id: self.id });
+
+        let log = self
+            .log
+            .clone()
+            .context(LogNotFound { alias: &self.alias })?;
+
+        Ok(log)
+    }
+
+    /// Runs the script again with the same arguments, config file and working directory. The run
+    /// happens in a pier process started in that directory.
+    pub fn rerun(&self, verbose: bool, options: RunOptions) -> PierResult<RunOutcome> {
+        fs::metadata(&self.cwd).context(ChangeDirectory { path: &self.cwd })?;
+
+        let pier = Pier::from_file(self.config.clone(), verbose)?;
+        let mut command = pier.pier_command()?;
+        command.current_dir(&self.cwd).arg("run");
+        if options.parallel {
+            command.arg("--parallel");
+        }
+        if options.log {
+            command.arg("--log");
+        }
+        command.arg(&self.alias).arg("--").args(&self.args);
+
+        let started = Instant::now();
+        let output = match options.passthrough {
+            true => Output {
+                status: command.status().context(CommandExec)?,
+                stdout: vec![],
+                stderr: vec![],
+            },
+            false => command.output().context(CommandExec)?,
+        };
+
+        Ok(RunOutcome::new(output, started.elapsed()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct HistoryFile {
+    #[serde(default)]
+    runs: Vec<RunRecord>,
+}
+
+/// Append-only store of the script runs.
+#[derive(Debug)]
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: PathBuf) -> Self {
+        History { path }
+    }
+
+    /// Opens the history in the pier state directory.
+    pub fn open() -> PierResult<Self> {
+        Ok(History::new(state_dir()?.join(HISTORY_FILE)))
+    }
+
+    /// Reads all records from oldest to newest. A missing history file has no records.
+    pub fn records(&self) -> PierResult<Vec<RunRecord>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+
+        let content = fs::read_to_string(&self.path).context(HistoryRead { path: &self.path })?;
+        let history: HistoryFile =
+            toml::from_str(&content).context(HistoryParse { path: &self.path })?;
+
+        Ok(history
+            .runs
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut record)| {
+                record.id = index + 1;
+                record
+            })
+            .collect())
+    }
+
+    /// Fetches the record with the given run number.
+    pub fn get(&self, id: usize) -> PierResult<RunRecord> {
+        let record = self
+            .records()?
+            .into_iter()
+            .find(|record| record.id == id)
+            .context(RunNotFound { id })?;
+
+        Ok(record)
+    }
+
+    /// Appends a record to the end of the history file.
+    pub fn append(&self, record: RunRecord) -> PierResult<()> {
+        if let Some(parent_dir) = self.path.parent() {
+            fs::create_dir_all(parent_dir).context(CreateDirectory)?;
+        }
+
+        let entry = toml::to_string(&HistoryFile { runs: vec![record] }).context(TomlSerialize)?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context(HistoryWrite { path: &self.path })?;
+
+        writeln!(file, "{}", entry).context(HistoryWrite { path: &self.path })?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,583 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+use snafu::{ensure, OptionExt, ResultExt};
+
+use super::error::*;
+use super::script::{shebang_interpreter, Script};
+use super::{home, pier_err, PierResult};
+
+/// Creates a script for every executable in the directory and its subdirectories, ordered by
+/// path. The scripts reference the files, or hold their content as command when inline is set
+/// and the file is text.
+///
+/// The description is taken from the comment block at the top of the file, and the tags are the
+/// subdirectories the file is in and the language of its shebang.
+pub fn scan_dir(dir: &Path, inline: bool) -> PierResult<Vec<Script>> {
+    let mut files = vec![];
+    find_executables(dir, &mut files)?;
+    files.sort();
+
+    let mut scripts: Vec<Script> = vec![];
+    for path in files {
+        let alias = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(alias) => alias.to_string(),
+            None => continue,
+        };
+        // Files with the same name in another subdirectory keep the first one.
+        if scripts.iter().any(|script| script.alias == alias) {
+            continue;
+        }
+
+        // Binaries have no text to look at.
+        let content = fs::read(&path)
+            .context(ImportRead { path: &path })
+            .map(|bytes| String::from_utf8(bytes).ok())?;
+
+        let mut tags: Vec<String> = path
+            .strip_prefix(dir)
+            .ok()
+            .and_then(Path::parent)
+            .map(|parent| {
+                parent
+                    .iter()
+                    .map(|dir| dir.to_string_lossy().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        if let Some(language) = content.as_deref().and_then(shebang_language) {
+            tags.push(language);
+        }
+
+        let (command, reference) = match &content {
+            Some(content) if inline => (content.clone(), None),
+            _ => (String::new(), Some(reference_of(&path))),
+        };
+
+        scripts.push(Script {
+            alias,
+            command,
+            reference,
+            description: content.as_deref().and_then(leading_comment),
+            tags: Some(tags).filter(|tags| !tags.is_empty()),
+            ..Script::default()
+        });
+    }
+
+    Ok(scripts)
+}
+
+/// Collects the executable files, skipping hidden files and directories.
+fn find_executables(dir: &Path, files: &mut Vec<PathBuf>) -> PierResult<()> {
+    let entries = fs::read_dir(dir).context(ImportRead { path: dir })?;
+
+    for entry in entries {
+        let path = entry.context(ImportRead { path: dir })?.path();
+        let hidden = path
+            .file_name()
+            .map_or(true, |name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+
+        if path.is_dir() {
+            find_executables(&path, files)?;
+        } else if path.metadata().map_or(false, |meta| {
+            meta.is_file() && meta.permissions().mode() & 0o111 != 0
+        }) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// The path of the file for the reference field, relative to the home directory when it's in
+/// there so the config keeps working for other users.
+pub(crate) fn reference_of(path: &Path) -> String {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+    match dirs::home_dir().and_then(|home| path.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(relative) => format!("~/{}", relative.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// The language of the shebang, without the version like python for python3.
+fn shebang_language(content: &str) -> Option<String> {
+    let interpreter = shebang_interpreter(content)?;
+    let language = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+    Some(language.to_string()).filter(|language| !language.is_empty())
+}
+
+/// The first paragraph of the comments at the top of the file, after the shebang, as a single
+/// line.
+fn leading_comment(content: &str) -> Option<String> {
+    let mut paragraph: Vec<&str> = vec![];
+
+    for line in content.lines().skip_while(|line| line.starts_with("#!")) {
+        let comment = match line.trim() {
+            line if line.starts_with("//") => line.trim_start_matches('/').trim(),
+            line if line.starts_with('#') => line.trim_start_matches('#').trim(),
+            _ => break,
+        };
+
+        // Editor and linter directives are not part of the description.
+        let directive = comment.starts_with("-*-")
+            || comment.starts_with("shellcheck ")
+            || comment.starts_with("vim:");
+        match (comment.is_empty() || directive, paragraph.is_empty()) {
+            (true, true) => continue,
+            (true, false) => break,
+            (false, _) => paragraph.push(comment),
+        }
+    }
+
+    Some(paragraph.join(" ")).filter(|description| !description.is_empty())
+}
+
+/// A shell with a history file to import from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(shell: &str) -> Result<Self, Self::Err> {
+        match shell {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(format!("Unknown shell {}, use bash, zsh or fish", shell)),
+        }
+    }
+}
+
+impl Shell {
+    /// The shell in $SHELL.
+    pub fn current() -> PierResult<Self> {
+        let shell = env::var("SHELL").context(NoDefaultShell)?;
+        let name = shell.rsplit('/').next().unwrap_or_default();
+
+        name.parse().map_err(|_| {
+            PierError::UnsupportedShell {
+                shell: name.to_string(),
+            }
+        })
+    }
+
+    /// The history file of the shell, $HISTFILE for bash and zsh when it's set.
+    pub fn history_file(self) -> Option<PathBuf> {
+        match self {
+            Shell::Bash | Shell::Zsh if env::var_os("HISTFILE").is_some() => {
+                env::var_os("HISTFILE").map(PathBuf::from)
+            }
+            Shell::Bash => home!(".bash_history"),
+            Shell::Zsh => {
+                match env::var_os("ZDOTDIR") {
+                    Some(dir) => Some(PathBuf::from(dir).join(".zsh_history")),
+                    None => home!(".zsh_history"),
+                }
+            }
+            Shell::Fish => {
+                let data_home = env::var_os("XDG_DATA_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| home!(".local/share"))?;
+                Some(data_home.join("fish/fish_history"))
+            }
+        }
+    }
+}
+
+/// Reads the commands of a history file, from oldest to newest.
+pub fn read_history(shell: Shell, path: &Path) -> PierResult<Vec<String>> {
+    // Zsh writes some characters in its own encoding, which shouldn't stop the import.
+    let content = fs::read(path).context(ImportRead { path })?;
+
+    Ok(parse_history(shell, &String::from_utf8_lossy(&content)))
+}
+
+/// Parses the commands of a history file, from oldest to newest.
+pub fn parse_history(shell: Shell, content: &str) -> Vec<String> {
+    match shell {
+        // Lines of timestamps start with # when HISTTIMEFORMAT is set.
+        Shell::Bash => {
+            content
+                .lines()
+                .filter(|line| {
+                    !(line.starts_with('#') && line[1..].chars().all(|c| c.is_ascii_digit()))
+                })
+                .map(str::to_string)
+                .collect()
+        }
+        // Extended history puts ": <time>:<duration>;" in front of the command, and commands
+        // with more lines end every line but the last with a backslash.
+        Shell::Zsh => {
+            let mut commands: Vec<String> = vec![];
+            let mut continued = false;
+            for line in content.lines() {
+                let line = match (continued, line.strip_prefix(": ")) {
+                    (false, Some(extended)) => {
+                        extended
+                            .split_once(';')
+                            .map_or(line, |(_, command)| command)
+                    }
+                    _ => line,
+                };
+                let (line, continues) = match line.strip_suffix('\\') {
+                    Some(line) => (line, true),
+                    None => (line, false),
+                };
+
+                match (continued, commands.last_mut()) {
+                    (true, Some(command)) => {
+                        command.push('\n');
+                        command.push_str(line);
+                    }
+                    _ => commands.push(line.to_string()),
+                }
+                continued = continues;
+            }
+            commands
+        }
+        Shell::Fish => {
+            content
+                .lines()
+                .filter_map(|line| line.strip_prefix("- cmd: "))
+                .map(|command| command.replace("\\n", "\n").replace("\\\\", "\\"))
+                .collect()
+        }
+    }
+}
+
+/// Commands that are too common to be worth a script.
+const TRIVIAL_COMMANDS: [&str; 12] = [
+    "cd", "ls", "ll", "la", "pwd", "clear", "exit", "history", "pier", "man", "cat", "vim",
+];
+
+/// A command of the shell history with how often it was used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub command: String,
+    pub count: usize,
+    pub score: f64,
+}
+
+/// Ranks the commands of the history, commands which are long or often repeated first. Trivial
+/// commands like cd or ls with a single argument are left out.
+pub fn rank_history(commands: &[String]) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = vec![];
+    let mut indexes: HashMap<&str, usize> = HashMap::new();
+    for command in commands {
+        let command = command.trim();
+        let mut words = command.split_whitespace();
+        match words.next() {
+            Some(program) if !TRIVIAL_COMMANDS.contains(&program) => (),
+            _ => continue,
+        }
+        if words.next().is_none() {
+            continue;
+        }
+
+        match indexes.get(command) {
+            Some(&index) => entries[index].count += 1,
+            None => {
+                indexes.insert(command, entries.len());
+                entries.push(HistoryEntry {
+                    command: command.to_string(),
+                    count: 1,
+                    score: 0.0,
+                })
+            }
+        }
+    }
+
+    for entry in &mut entries {
+        entry.score = entry.count as f64 * (1.0 + entry.command.len() as f64 / 40.0);
+    }
+    // Sorting is stable so ties keep the order of the history.
+    entries.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+
+    entries
+}
+
+/// The last command of the history which isn't pier add itself, as the shell may have written
+/// that one already.
+pub fn last_command(commands: &[String]) -> PierResult<String> {
+    let command = commands
+        .iter()
+        .rev()
+        .find(|command| {
+            let words: Vec<&str> = command.split_whitespace().take(2).collect();
+            !words.is_empty() && words != ["pier", "add"]
+        })
+        .context(HistoryEmpty)?;
+
+    Ok(command.clone())
+}
+
+/// Suggests an alias for a command from its first words, like docker-system-prune for
+/// `sudo docker system prune -a -f`.
+pub fn suggest_alias(command: &str) -> String {
+    let words: Vec<String> = command
+        .split_whitespace()
+        .skip_while(|word| *word == "sudo" || word.contains('='))
+        .take_while(|word| !["|", "&&", "||", ";", ">"].contains(word))
+        .filter(|word| !word.starts_with('-'))
+        .map(|word| word.rsplit('/').next().unwrap_or(word))
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .filter(|word| !word.is_empty())
+        .take(3)
+        .collect();
+
+    match words.is_empty() {
+        true => String::from("script"),
+        false => words.join("-"),
+    }
+}
+
+/// Reads the commands in the history of the shell, or in the given history file. Without a
+/// shell the one in $SHELL is used.
+pub fn shell_history(shell: Option<Shell>, file: Option<PathBuf>) -> PierResult<Vec<String>> {
+    let shell = match shell {
+        Some(shell) => shell,
+        None => Shell::current()?,
+    };
+    let path = match file.or_else(|| shell.history_file()) {
+        Some(path) => path,
+        None => pier_err!(PierError::HistoryEmpty),
+    };
+
+    let commands = read_history(shell, &path)?;
+    ensure!(!commands.is_empty(), HistoryEmpty);
+
+    Ok(commands)
+}
+
+/// A kind of project file with tasks to import.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskSource {
+    Make,
+    Just,
+    Npm,
+}
+
+impl TaskSource {
+    /// The name of the source, which is also the tag of its scripts.
+    pub fn name(self) -> &'static str {
+        match self {
+            TaskSource::Make => "make",
+            TaskSource::Just => "just",
+            TaskSource::Npm => "npm",
+        }
+    }
+
+    /// The file of the source in the current directory.
+    pub fn default_file(self) -> PathBuf {
+        let name = match self {
+            TaskSource::Make => "Makefile",
+            TaskSource::Just => "justfile",
+            TaskSource::Npm => "package.json",
+        };
+
+        PathBuf::from(name)
+    }
+}
+
+/// A task of a project file, with the comment or command that describes it.
+#[derive(Debug, Clone, PartialEq)]
+struct Task {
+    name: String,
+    description: Option<String>,
+}
+
+/// Creates a script for every task of a Makefile, justfile or package.json. The scripts run the
+/// task in the directory of the file and reference the file. Their aliases are the task names,
+/// prefixed by the given prefix.
+pub fn scan_tasks(
+    source: TaskSource,
+    file: &Path,
+    prefix: Option<&str>,
+) -> PierResult<Vec<Script>> {
+    let content = fs::read_to_string(file).context(ImportRead { path: file })?;
+    let path = fs::canonicalize(file).context(ImportRead { path: file })?;
+    let project = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let tasks = match source {
+        TaskSource::Make => make_targets(&content),
+        TaskSource::Just => just_recipes(&content),
+        TaskSource::Npm => npm_scripts(&content).context(ImportJson { path: file })?,
+    };
+
+    // The default file names are found by the tools without naming them.
+    let is_default = PathBuf::from(&file_name) == source.default_file()
+        || (source == TaskSource::Make
+            && ["makefile", "GNUmakefile"].contains(&file_name.as_str()))
+        || (source == TaskSource::Just && file_name.eq_ignore_ascii_case("justfile"));
+    let program = match (source, is_default) {
+        (TaskSource::Make, true) => String::from("make"),
+        (TaskSource::Make, false) => format!("make -f '{}'", file_name),
+        (TaskSource::Just, true) => String::from("just"),
+        (TaskSource::Just, false) => format!("just --justfile '{}'", file_name),
+        (TaskSource::Npm, _) => String::from("npm run"),
+    };
+
+    let scripts = tasks
+        .into_iter()
+        .map(|task| {
+            let command = match source {
+                TaskSource::Make => format!("{} {}", program, task.name),
+                TaskSource::Just => format!("{} {} \"$@\"", program, task.name),
+                TaskSource::Npm => format!("{} {} -- \"$@\"", program, task.name),
+            };
+            Script {
+                alias: match prefix {
+                    Some(prefix) if !prefix.is_empty() => format!("{}-{}", prefix, task.name),
+                    _ => task.name.clone(),
+                },
+                command,
+                description: task.description,
+                reference: Some(reference_of(&path)),
+                cwd: Some(project.clone()),
+                tags: Some(vec![source.name().to_string()]),
+                ..Script::default()
+            }
+        })
+        .collect();
+
+    Ok(scripts)
+}
+
+/// The targets of a Makefile, described by the comment above them or a ## comment after them.
+/// Special targets, pattern rules and variables are left out.
+fn make_targets(content: &str) -> Vec<Task> {
+    let mut tasks: Vec<Task> = vec![];
+    let mut comment: Option<String> = None;
+
+    for line in content.lines() {
+        if let Some(text) = line.strip_prefix('#') {
+            comment = Some(text.trim_start_matches('#').trim().to_string());
+            continue;
+        }
+
+        let rule = match line.split_once(':') {
+            Some((targets, rest))
+                if !line.starts_with(|c: char| c.is_whitespace())
+                    && !targets.contains('=')
+                    && !rest.starts_with('=')
+                    && !rest.starts_with(":=") =>
+            {
+                Some((targets, rest))
+            }
+            _ => None,
+        };
+
+        if let Some((targets, rest)) = rule {
+            let description = match rest.split_once("##") {
+                Some((_, description)) => Some(description.trim().to_string()),
+                None => comment.clone(),
+            };
+            for target in targets.split_whitespace() {
+                if target.starts_with('.')
+                    || target.contains('%')
+                    || target.contains('$')
+                    || tasks.iter().any(|task| task.name == target)
+                {
+                    continue;
+                }
+                tasks.push(Task {
+                    name: target.to_string(),
+                    description: description.clone().filter(|d| !d.is_empty()),
+                });
+            }
+        }
+        comment = None;
+    }
+
+    tasks
+}
+
+/// The public recipes of a justfile, described by the comment above them.
+fn just_recipes(content: &str) -> Vec<Task> {
+    let mut tasks: Vec<Task> = vec![];
+    let mut comment: Option<String> = None;
+    let mut private = false;
+
+    for line in content.lines() {
+        if let Some(text) = line.strip_prefix('#') {
+            comment = Some(text.trim().to_string());
+            continue;
+        }
+        // Attributes like [private] sit between the comment and the recipe.
+        if line.starts_with('[') {
+            private |= line.contains("private");
+            continue;
+        }
+
+        let first = line.split_whitespace().next().unwrap_or_default();
+        let keyword = ["set", "alias", "export", "import", "mod"].contains(&first);
+        let is_recipe = !line.starts_with(|c: char| c.is_whitespace())
+            && !keyword
+            && line.contains(':')
+            && !line.contains(":=");
+        if is_recipe {
+            let name = line
+                .trim_start_matches('@')
+                .split(|c: char| c.is_whitespace() || c == ':')
+                .next()
+                .unwrap_or_default();
+            if !name.is_empty() && !name.starts_with('_') && !private {
+                tasks.push(Task {
+                    name: name.to_string(),
+                    description: comment.clone().filter(|d| !d.is_empty()),
+                });
+            }
+        }
+        comment = None;
+        private = false;
+    }
+
+    tasks
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct PackageJson {
+    #[serde(default)]
+    scripts: BTreeMap<String, String>,
+}
+
+/// The scripts of a package.json, described by the command they run.
+fn npm_scripts(content: &str) -> Result<Vec<Task>, serde_json::Error> {
+    let package: PackageJson = serde_json::from_str(content)?;
+
+    Ok(package
+        .scripts
+        .into_iter()
+        .map(|(name, command)| {
+            Task {
+                name,
+                description: Some(command),
+            }
+        })
+        .collect())
+}
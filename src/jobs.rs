@@ -0,0 +1,295 @@
+use std::fs;
+use std::mem;
+use std::ptr;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, OptionExt, ResultExt};
+use tempfile::NamedTempFile;
+
+use super::defaults::*;
+use super::error::*;
+use super::logs::{mark_killed, print_log, RunLog};
+use super::{exit_code, Pier, PierResult, RunOptions};
+
+/// A script running in the background under a supervisor.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Job {
+    // The number of the job, taken from the name of the job file.
+    #[serde(skip)]
+    pub id: usize,
+    pub alias: String,
+    pub args: Vec<String>,
+    pub config: PathBuf,
+    pub cwd: PathBuf,
+    pub parallel: bool,
+    pub started: DateTime<Local>,
+    // The supervisor leads the process group of the job, set once it has started.
+    pub pid: Option<u32>,
+    pub log: Option<PathBuf>,
+    pub finished: Option<DateTime<Local>>,
+    pub exit_code: Option<i32>,
+    // The last signal sent by pier kill, which doesn't end the job unless the script exits.
+    pub signal: Option<i32>,
+    // Set when the supervisor failed to run the script.
+    pub error: Option<String>,
+}
+
+impl Job {
+    /// Whether the supervisor of the job is still alive.
+    pub fn is_running(&self) -> bool {
+        match (self.finished, self.pid) {
+            (None, Some(pid)) => Path::new("/proc").join(pid.to_string()).exists(),
+            _ => false,
+        }
+    }
+
+    pub fn status(&self) -> String {
+        match (&self.error, self.finished, self.exit_code, self.signal) {
+            (Some(_), _, _, _) => String::from("failed"),
+            // The script ended with the signal rather than handling it and going on.
+            (None, Some(_), Some(code), Some(signal)) if code == 128 + signal => {
+                format!("killed ({})", signal_name(signal))
+            }
+            (None, Some(_), Some(code), _) => format!("exited {}", code),
+            (None, Some(_), None, _) => String::from("exited by signal"),
+            (None, None, _, _) => {
+                match (self.pid, self.is_running()) {
+                    (None, _) => String::from("starting"),
+                    (Some(_), true) => String::from("running"),
+                    (Some(_), false) => String::from("lost"),
+                }
+            }
+        }
+    }
+}
+
+/// Store of the jobs, one file per job in the pier state directory.
+#[derive(Debug)]
+pub struct Jobs {
+    dir: PathBuf,
+}
+
+impl Jobs {
+    pub fn new(dir: PathBuf) -> Self {
+        Jobs { dir }
+    }
+
+    /// Opens the jobs in the pier state directory.
+    pub fn open() -> PierResult<Self> {
+        Ok(Jobs::new(state_dir()?.join(JOBS_DIR)))
+    }
+
+    fn job_path(&self, id: usize) -> PathBuf {
+        self.dir.join(format!("{}.toml", id))
+    }
+
+    /// All jobs ordered by their number.
+    pub fn list(&self) -> PierResult<Vec<Job>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut ids: Vec<usize> = fs::read_dir(&self.dir)
+            .context(JobRead { path: &self.dir })?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                match path.extension() {
+                    Some(ext) if ext == "toml" => path.file_stem()?.to_str()?.parse().ok(),
+                    _ => None,
+                }
+            })
+            .collect();
+        ids.sort_unstable();
+
+        ids.into_iter().map(|id| self.get(id)).collect()
+    }
+
+    /// Fetches the job with the given number.
+    pub fn get(&self, id: usize) -> PierResult<Job> {
+        let path = self.job_path(id);
+        ensure!(path.exists(), JobNotFound { id });
+
+        let content = fs::read_to_string(&path).context(JobRead { path: &path })?;
+        let mut job: Job = toml::from_str(&content).context(JobParse { path: &path })?;
+        job.id = id;
+
+        Ok(job)
+    }
+
+    /// Writes the job to its file. The file is replaced in one go so readers never see a
+    /// partially written job.
+    pub fn save(&self, job: &Job) -> PierResult<()> {
+        fs::create_dir_all(&self.dir).context(CreateDirectory)?;
+
+        let path = self.job_path(job.id);
+        let tmp_path = path.with_extension("toml.tmp");
+        let content = toml::to_string(job).context(TomlSerialize)?;
+
+        fs::write(&tmp_path, content).context(JobWrite { path: &tmp_path })?;
+        fs::rename(&tmp_path, &path).context(JobWrite { path: &path })?;
+
+        Ok(())
+    }
+
+    /// Stores a new job with the next free number. The job file is only created when no other
+    /// pier took the number in the meantime, otherwise the next number is tried.
+    pub fn create(&self, mut job: Job) -> PierResult<Job> {
+        fs::create_dir_all(&self.dir).context(CreateDirectory)?;
+
+        job.id = self.list()?.last().map_or(1, |last| last.id + 1);
+        let content = toml::to_string(&job).context(TomlSerialize)?;
+        loop {
+            let mut tmp_file =
+                NamedTempFile::new_in(&self.dir).context(JobWrite { path: &self.dir })?;
+            tmp_file
+                .write_all(content.as_bytes())
+                .context(JobWrite { path: tmp_file.path() })?;
+
+            // Linking the written file fails when the job file exists, so readers never see a
+            // partially written job.
+            let path = self.job_path(job.id);
+            match tmp_file.persist_noclobber(&path) {
+                Ok(_) => return Ok(job),
+                Err(err) if err.error.kind() == io::ErrorKind::AlreadyExists => job.id += 1,
+                Err(err) => return Err(err.error).context(JobWrite { path: &path }),
+            }
+        }
+    }
+
+    /// Sends a signal to the process group of a running job.
+    pub fn kill(&self, id: usize, signal: i32) -> PierResult<Job> {
+        let mut job = self.get(id)?;
+        ensure!(job.is_running(), JobNotRunning { id });
+
+        let pid = job.pid.context(JobNotRunning { id })?;
+        // Saved before the signal is sent, so the supervisor finds it once the script has exited.
+        let previous = job.signal.replace(signal);
+        self.save(&job)?;
+
+        // The supervisor was started as the leader of a new process group, so the negative pid
+        // signals the supervisor and every process of the script.
+        if unsafe { libc::kill(-(pid as i32), signal) } != 0 {
+            let err = io::Error::last_os_error();
+            job.signal = previous;
+            self.save(&job)?;
+            return Err(err).context(JobSignal { id });
+        }
+
+        // The supervisor outlives every other signal and records the end of the job itself, but
+        // dies with the script on SIGKILL.
+        if signal == libc::SIGKILL {
+            job.finished = Some(Local::now());
+            job.exit_code = Some(128 + signal);
+            self.save(&job)?;
+            if let Some(log) = &job.log {
+                mark_killed(log, signal)?;
+            }
+        }
+
+        Ok(job)
+    }
+
+    /// Prints the output of a job and keeps following it until the job has finished.
+    pub fn attach(&self, id: usize) -> PierResult<()> {
+        loop {
+            let job = self.get(id)?;
+            if let Some(log) = &job.log {
+                return print_log(log, true);
+            }
+            ensure!(job.pid.is_none() || job.is_running(), JobNotRunning { id });
+
+            // The supervisor hasn't created the log yet.
+            thread::sleep(Duration::from_millis(LOG_FOLLOW_INTERVAL_MS));
+        }
+    }
+
+    /// Runs the script of a job and records the outcome. This runs inside the supervisor process,
+    /// which is started in the working directory of the job.
+    pub fn supervise(&self, id: usize, verbose: bool) -> PierResult<()> {
+        let mut job = self.get(id)?;
+        outlive_signals();
+
+        let result = Pier::from_file(job.config.clone(), verbose).and_then(|pier| {
+            let log = RunLog::create(&job.alias)?;
+            job.pid = Some(process::id());
+            job.log = Some(log.path.clone());
+            if let Err(err) = self.save(&job) {
+                log.fail(&err)?;
+                return Err(err);
+            }
+
+            let options = RunOptions {
+                parallel: job.parallel,
+                // The output only goes to the log, the supervisor has no terminal.
+                passthrough: true,
+                ..RunOptions::default()
+            };
+            pier.run_script_with_log(&job.alias, job.args.clone(), options, Some(log))
+        });
+
+        // Keeps the signal pier kill stored while the script was running.
+        job.signal = self.get(id)?.signal;
+        job.finished = Some(Local::now());
+        match result {
+            Ok(outcome) => job.exit_code = exit_code(&outcome.status),
+            Err(err) => job.error = Some(err.to_string()),
+        };
+        self.save(&job)?;
+
+        Ok(())
+    }
+}
+
+extern "C" fn handle_signal(_signal: libc::c_int) {}
+
+/// Lets the supervisor live on when pier kill signals the process group of the job, so it can
+/// record how the script ended. The signals are handled rather than ignored, as the script gets
+/// the default of handled signals back when it starts while ignored signals stay ignored.
+fn outlive_signals() {
+    for (_, signal) in SIGNALS.iter().filter(|(_, signal)| *signal != libc::SIGKILL) {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handle_signal as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigaction(*signal, &action, ptr::null_mut());
+        }
+    }
+}
+
+/// Parses a signal given by name, with or without the SIG prefix, or by number.
+pub fn parse_signal(signal: &str) -> Result<i32, String> {
+    if let Ok(number) = signal.parse() {
+        return Ok(number);
+    }
+
+    let name = signal.trim_start_matches("SIG");
+    SIGNALS
+        .iter()
+        .find(|(signal_name, _)| signal_name.eq_ignore_ascii_case(name))
+        .map(|(_, number)| *number)
+        .ok_or_else(|| format!("Unknown signal {}", signal))
+}
+
+fn signal_name(signal: i32) -> String {
+    match SIGNALS.iter().find(|(_, number)| *number == signal) {
+        Some((name, _)) => format!("SIG{}", name),
+        None => signal.to_string(),
+    }
+}
+
+const SIGNALS: &[(&str, i32)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("TERM", libc::SIGTERM),
+];
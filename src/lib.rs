@@ -1,55 +1,140 @@
-use prettytable::{cell, row, Table};
 use snafu::{ensure, OptionExt, ResultExt};
-use std::fs;
-use std::{path::PathBuf, process::ExitStatus};
+use chrono::Local;
+use std::cmp::Ordering;
+use std::{env, fs};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Output, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+pub mod build;
+pub mod bundle;
 pub mod cli;
-mod config;
+pub mod config;
+pub mod docs;
 pub mod error;
-use config::Config;
+pub mod events;
+use events::{Event, EventHandler};
+use config::{Config, Source};
 mod defaults;
+mod dependencies;
+use dependencies::execution_layers;
+pub mod export;
+pub mod history;
+pub mod import;
+pub mod schedule;
+pub mod serve;
+pub mod shims;
+pub mod sources;
+use sources::merge_sources;
+pub mod rpc;
+pub mod watch;
+use watch::WatchOpts;
+pub mod jobs;
+use jobs::{Job, Jobs};
+pub mod logs;
+use logs::RunLog;
+use history::{History, RunRecord};
+pub mod usage;
+use usage::{usage_by_alias, SortOrder, Usage};
 mod macros;
 use defaults::*;
 pub mod script;
+pub mod store;
+use store::{DirectoryStore, ScriptStore, TomlStore};
 use error::*;
-use scrawl;
-use script::Script;
+use script::{Hooks, OutputTarget, Script, Step};
 
 // Creates a Result type that return PierError by default
 pub type PierResult<T, E = PierError> = ::std::result::Result<T, E>;
 
+/// Options for a single run of a script.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RunOptions {
+    // Run independent prerequisites of the script in parallel.
+    pub parallel: bool,
+    // Capture the output in a run log, even if the script doesn't enable logging itself.
+    pub log: bool,
+    // Pass the output of the scripts through to the terminal instead of capturing it in the
+    // outcome.
+    pub passthrough: bool,
+}
+
+/// The result of running a script.
+#[derive(Debug, Clone)]
+pub struct RunOutcome {
+    pub status: ExitStatus,
+    // The output of the script, its prerequisites and hooks, empty when it was passed through.
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub duration: Duration,
+}
+
+impl RunOutcome {
+    fn new(output: Output, duration: Duration) -> Self {
+        RunOutcome {
+            status: output.status,
+            stdout: output.stdout,
+            stderr: output.stderr,
+            duration,
+        }
+    }
+}
+
 /// Main library interface
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Pier {
     config: Config,
+    store: Box<dyn ScriptStore>,
     path: PathBuf,
     verbose: bool,
+    events: Option<EventHandler>,
+    // Events from before there was a handler, like skipped sources while loading.
+    pending_events: Vec<Event>,
 }
 
-#[macro_use]
-extern crate lazy_static;
-
-use prettytable::format::LineSeparator;
-use prettytable::format::LinePosition;
-use prettytable::format::FormatBuilder;
-use prettytable::format::TableFormat;
-
-lazy_static! {
-    static ref COOL_SEP: LineSeparator = LineSeparator::new('\u{2256}', '\u{2256}', '\u{2256}', '\u{2256}');
-
-    pub static ref COOL_FORMAT: TableFormat = FormatBuilder::new()
-      .column_separator('\u{22EE}')
-      .borders('\u{22EE}')
-      .separator(LinePosition::Title, *COOL_SEP)
-      .separator(LinePosition::Bottom, *COOL_SEP)
-      .separator(LinePosition::Top, *COOL_SEP)
-      .padding(1, 1)
-      .build();
+impl Default for Pier {
+    fn default() -> Self {
+        Pier {
+            config: Config::default(),
+            store: Box::new(TomlStore::new(PathBuf::new())),
+            path: PathBuf::new(),
+            verbose: false,
+            events: None,
+            pending_events: vec![],
+        }
+    }
 }
 
 impl Pier {
-    /// Wrapper to write the configuration to path.
-    pub fn write(&self) -> PierResult<()> {
-        self.config.write(&self.path)?;
+    /// Calls the handler with every event of runs, the scheduler and the HTTP API server, and
+    /// with the events from loading the config.
+    pub fn on_event<F: Fn(&Event) + Send + Sync + 'static>(&mut self, handler: F) {
+        self.set_event_handler(EventHandler::new(handler));
+    }
+
+    pub(crate) fn set_event_handler(&mut self, handler: EventHandler) {
+        self.events = Some(handler);
+        for event in std::mem::take(&mut self.pending_events) {
+            self.emit(event);
+        }
+    }
+
+    fn emit(&self, event: Event) {
+        if let Some(events) = &self.events {
+            events.emit(event);
+        }
+    }
+
+    /// Wrapper to save the configuration to the store.
+    pub fn write(&mut self) -> PierResult<()> {
+        // Scripts of sources stay in their repositories.
+        let mut config = self.config.clone();
+        config.scripts.retain(|_, script| script.source.is_none());
+        self.store.save(&config)?;
 
         Ok(())
     }
@@ -67,14 +152,14 @@ impl Pier {
                 fs::create_dir(parent_dir).context(CreateDirectory)?;
             }
         };
+        self.store = Box::new(TomlStore::new(self.path.clone()));
 
-        &self.add_script(Script {
+        self.add_script(Script {
             alias: String::from("hello-pier"),
             command: String::from("echo Hello, Pier!"),
             description: Some(String::from("This is an example command.")),
-            reference: None,
-            tags: None,
-        }, false);
+            ..Script::default()
+        }, false)?;
 
         self.write()?;
 
@@ -85,12 +170,39 @@ impl Pier {
         Pier::default()
     }
 
-    /// Create new pier directly from path.
+    /// The config with the scripts.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The config with the scripts, changes are saved by write.
+    pub fn config_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    /// The path of the config file or directory, empty for stores without a path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Create new pier directly from path, which is either a config file or a directory with a
+    /// file for every script.
     pub fn from_file(path: PathBuf, verbose: bool) -> PierResult<Self> {
+        Pier::with_store(open_store(path), verbose)
+    }
+
+    /// Create new pier which loads its scripts from the store and saves them to it.
+    pub fn with_store(store: Box<dyn ScriptStore>, verbose: bool) -> PierResult<Self> {
+        let mut config = store.load()?;
+        let pending_events = merge_sources(&mut config);
+
         let pier = Self {
-            config: Config::from(&path)?,
+            config,
+            path: store.location().map(Path::to_path_buf).unwrap_or_default(),
+            store,
             verbose,
-            path,
+            events: None,
+            pending_events,
         };
         Ok(pier)
     }
@@ -106,54 +218,112 @@ impl Pier {
         Ok(pier)
     }
 
+    /// The sources of the config at the path, or at the first existing default path, without
+    /// loading them, so sources can be updated even when one of them is broken.
+    pub fn read_sources(input_path: Option<PathBuf>) -> PierResult<Vec<Source>> {
+        let path = match input_path {
+            Some(path) => path,
+            None => fallback_path()?,
+        };
+        Ok(open_store(path).load()?.sources)
+    }
+
+    /// The full alias of the script the alias refers to, which can be the end of an alias in a
+    /// group as long as only one script ends with it.
+    pub fn resolve_alias(&self, alias: &str) -> PierResult<String> {
+        ensure!(!self.config.scripts.is_empty(), NoScriptsExists);
+
+        if let Some(resolved) = self.config.scripts.resolve(alias) {
+            return Ok(resolved.to_string());
+        }
+
+        let matches = self.config.scripts.suffix_matches(alias);
+        ensure!(
+            matches.is_empty(),
+            AmbiguousAlias {
+                alias,
+                matches: matches.iter().map(|alias| alias.to_string()).collect::<Vec<_>>(),
+            }
+        );
+
+        AliasNotFound { alias }.fail()
+    }
+
+    /// Resolves an alias which records like the run history or shims can have, which can be of a
+    /// script that doesn't exist anymore. Such aliases are returned as they are.
+    pub fn resolve_recorded_alias(&self, alias: &str) -> PierResult<String> {
+        match self.resolve_alias(alias) {
+            Err(PierError::AmbiguousAlias { alias, matches }) => {
+                Err(PierError::AmbiguousAlias { alias, matches })
+            }
+            Err(_) => Ok(alias.to_string()),
+            Ok(alias) => Ok(alias),
+        }
+    }
+
     /// Fetches a script that matches the alias
     pub fn fetch_script(&self, alias: &str) -> PierResult<&Script> {
-        ensure!(!self.config.scripts.is_empty(), NoScriptsExists);
+        let alias = self.resolve_alias(alias)?;
 
         let script = self
             .config
             .scripts
-            .get(alias)
-            .context(AliasNotFound {
-                alias: &alias.to_string(),
-            })?;
+            .get(&alias)
+            .context(AliasNotFound { alias: &alias })?;
 
         Ok(script)
     }
 
-    /// Edits a script that matches the alias
-    pub fn edit_script(&mut self, alias: &str) -> PierResult<&Script> {
-        ensure!(!self.config.scripts.is_empty(), NoScriptsExists);
+    /// Fails for scripts of sources, which can only be changed in their repository.
+    pub(crate) fn check_writable(&self, alias: &str) -> PierResult<()> {
+        let script = self.config.scripts.get(alias);
+        if let Some(git) = script.and_then(|script| script.source.as_ref()) {
+            pier_err!(PierError::ReadOnlyScript {
+                alias: alias.to_string(),
+                git: git.clone(),
+            });
+        }
 
-        let mut script =
+        Ok(())
+    }
+
+    /// Replaces the command of a script that matches the alias
+    pub fn edit_script(&mut self, alias: &str, command: String) -> PierResult<&Script> {
+        self.update_script(alias, |script| script.command = command)
+    }
+
+    /// Changes a script that matches the alias with the given function
+    pub fn update_script<F: FnOnce(&mut Script)>(
+        &mut self,
+        alias: &str,
+        update: F,
+    ) -> PierResult<&Script> {
+        let alias = self.resolve_alias(alias)?;
+        self.check_writable(&alias)?;
+
+        let script =
             self.config
                 .scripts
-                .get_mut(alias)
-                .context(AliasNotFound {
-                    alias: &alias.to_string(),
-                })?;
-
-        script.command = open_editor(Some(&script.command))?;
+                .get_mut(&alias)
+                .context(AliasNotFound { alias: &alias })?;
 
-        println!("Edited {}", &alias);
+        update(script);
 
         Ok(script)
     }
 
-    /// Removes a script that matches the alias
-    pub fn remove_script(&mut self, alias: &str) -> PierResult<()> {
-        ensure!(!self.config.scripts.is_empty(), NoScriptsExists);
+    /// Removes a script that matches the alias and returns it
+    pub fn remove_script(&mut self, alias: &str) -> PierResult<Script> {
+        let alias = self.resolve_alias(alias)?;
+        self.check_writable(&alias)?;
 
-        self.config
+        let script = self
+            .config
             .scripts
-            .remove(alias)
-            .context(AliasNotFound {
-                alias: &alias.to_string(),
-            })?;
+            .remove(&alias)
+            .context(AliasNotFound { alias: &alias })?;
 
-        println!("Removed {}", &alias);
-
-        Ok(())
+        Ok(script)
     }
 
     /// Adds a script that matches the alias
@@ -167,38 +337,84 @@ impl Pier {
             );
         }
 
-        println!("Added {}", &script.alias);
-
         self.config.scripts.insert(script.alias.to_string(), script);
 
         Ok(())
     }
 
-    /// Prints only the aliases in current config file that matches tags.
-    pub fn list_aliases(&self, tags: Option<Vec<String>>) -> PierResult<()> {
-        ensure!(!self.config.scripts.is_empty(), NoScriptsExists);
+    /// Returns the scripts in the given order, scripts with the same usage are ordered by alias.
+    fn sorted_scripts(&self, sort: SortOrder) -> Vec<(&String, &Script)> {
+        let mut scripts: Vec<(&String, &Script)> = self.config.scripts.iter().collect();
+        if sort == SortOrder::Name {
+            return scripts;
+        }
 
-        for (alias, script) in self.config.scripts.iter() {
-            match (&tags, &script.tags) {
-                (Some(list_tags), Some(script_tags)) => {
-                    for tag in list_tags {
-                        if script_tags.contains(tag) {
-                            println!("{}", alias);
+        // A broken history shouldn't hide the scripts, they are listed by name instead.
+        let records = match History::open().and_then(|history| history.records()) {
+            Ok(records) => records,
+            Err(err) => {
+                self.emit(Event::HistoryFailed {
+                    error: err.to_string(),
+                });
+                return scripts;
+            }
+        };
+        let usages = usage_by_alias(&records);
+        let unused = Usage::default();
+        let usage = |alias: &String| usages.get(alias).unwrap_or(&unused);
+
+        // Sorting is stable so ties keep the alphabetical order.
+        match sort {
+            SortOrder::Name => (),
+            SortOrder::Frecency => scripts.sort_by(|(a, _), (b, _)| {
+                usage(b)
+                    .frecency
+                    .partial_cmp(&usage(a).frecency)
+                    .unwrap_or(Ordering::Equal)
+            }),
+            SortOrder::LastRun => {
+                scripts.sort_by(|(a, _), (b, _)| usage(b).last_run.cmp(&usage(a).last_run))
+            }
+            SortOrder::Count => scripts.sort_by(|(a, _), (b, _)| usage(b).count.cmp(&usage(a).count)),
+        };
 
-                            continue;
-                        }
-                    }
-                }
-                (None, _) => {
-                    println!("{}", alias);
+        scripts
+    }
 
-                    continue;
-                }
-                _ => (),
-            };
+    /// The width of the commands when listing the scripts, the given width takes precedence over
+    /// the config.
+    pub fn command_width(&self, cmd_width: Option<usize>) -> usize {
+        match (cmd_width, self.config.default.command_width) {
+            (Some(width), _) => width,
+            (None, Some(width)) => width,
+            (None, None) => FALLBACK_COMMAND_DISPLAY_WIDTH,
         }
+    }
 
-        Ok(())
+    /// Returns the scripts in current config file that match one of the tags, in the given order.
+    pub fn list_scripts(
+        &self,
+        tags: Option<Vec<String>>,
+        sort: SortOrder,
+    ) -> PierResult<Vec<&Script>> {
+        ensure!(!self.config.scripts.is_empty(), NoScriptsExists);
+
+        let scripts = self
+            .sorted_scripts(sort)
+            .into_iter()
+            .map(|(_, script)| script)
+            .filter(|script| {
+                match (&tags, &script.tags) {
+                    (Some(list_tags), Some(script_tags)) => {
+                        list_tags.iter().any(|tag| script_tags.contains(tag))
+                    }
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                }
+            })
+            .collect();
+
+        Ok(scripts)
     }
 
     /// Copy an alias a script that matches the alias
@@ -209,20 +425,10 @@ impl Pier {
         );
 
         // TODO: refactor the line below.
-        let script = self
-            .config
-            .scripts
-            .get(from_alias)
-            .context(AliasNotFound {
-                alias: &from_alias.to_string(),
-            })?
-            .clone();
-
-        println!(
-            "Copy from alias {} to new alias {}",
-            &from_alias.to_string(),
-            &new_alias.to_string()
-        );
+        let mut script = self.fetch_script(from_alias)?.clone();
+        script.alias = new_alias.to_string();
+        // A copy of a script of a source belongs to the config.
+        script.source = None;
 
         self.config.scripts.insert(new_alias.to_string(), script);
 
@@ -238,175 +444,452 @@ impl Pier {
             );
         }
 
-        let script = self
+        let from_alias = self.resolve_alias(from_alias)?;
+        self.check_writable(&from_alias)?;
+
+        let mut script = self
             .config
             .scripts
-            .remove(from_alias)
-            .context(AliasNotFound {
-                alias: &from_alias.to_string(),
-            })?
-            .clone();
-
-        println!(
-            "Move from alias {} to new alias {}",
-            &from_alias.to_string(),
-            &new_alias.to_string()
-        );
+            .remove(&from_alias)
+            .context(AliasNotFound { alias: &from_alias })?;
+        script.alias = new_alias.to_string();
 
         self.config.scripts.insert(new_alias.to_string(), script);
 
         Ok(())
     }
 
-    /// Prints a terminal table of the scripts in current config file that matches tags.
-    pub fn list_scripts(
+    /// Creates a command which runs this pier executable with the same config file.
+    fn pier_command(&self) -> PierResult<Command> {
+        let exe = env::current_exe().context(CommandExec)?;
+        let mut command = Command::new(exe);
+        if self.verbose {
+            command.arg("--verbose");
+        }
+        command
+            .arg("--config-file")
+            .arg(fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone()));
+
+        Ok(command)
+    }
+
+    /// Runs a script and runs it again every time one of the watched files changes.
+    pub fn watch_script(&self, alias: &str, args: Vec<String>, opts: WatchOpts) -> PierResult<()> {
+        let script = self.fetch_script(alias)?;
+        let opts = opts.merge(script.watch.as_ref());
+        let base_command = self.pier_command()?;
+
+        watch::watch(
+            || {
+                let mut command = Command::new(base_command.get_program());
+                command
+                    .args(base_command.get_args())
+                    .arg("run")
+                    .arg(alias)
+                    .arg("--")
+                    .args(&args)
+                    // A process group of its own lets a new run terminate every process of the
+                    // previous one.
+                    .process_group(0);
+                command
+            },
+            &opts,
+        )
+    }
+
+    /// Starts a script in the background under a supervisor process and returns its job.
+    pub fn detach_script(&self, alias: &str, args: Vec<String>, parallel: bool) -> PierResult<Job> {
+        self.fetch_script(alias)?;
+
+        let jobs = Jobs::open()?;
+        let job = jobs.create(Job {
+            id: 0,
+            alias: alias.to_string(),
+            args,
+            config: fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone()),
+            cwd: env::current_dir().unwrap_or_default(),
+            parallel,
+            started: Local::now(),
+            pid: None,
+            log: None,
+            finished: None,
+            exit_code: None,
+            signal: None,
+            error: None,
+        })?;
+
+        let mut supervisor = self.pier_command()?;
+        supervisor
+            .arg("supervise")
+            .arg(job.id.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .current_dir(&job.cwd)
+            // A new process group lets pier kill reach every process of the job.
+            .process_group(0)
+            .spawn()
+            .context(CommandExec)?;
+
+        Ok(job)
+    }
+
+    /// Runs a script with its prerequisites wrapped by the default hooks and the hooks of the
+    /// script.
+    pub fn run_script(
         &self,
-        tags: Option<Vec<String>>,
-        cmd_full: bool,
-        cmd_width: Option<usize>,
-    ) -> PierResult<()> {
-        let width = match (cmd_width, self.config.default.command_width) {
-            (Some(width), _) => width,
-            (None, Some(width)) => width,
-            (None, None) => FALLBACK_COMMAND_DISPLAY_WIDTH,
+        alias: &str,
+        args: Vec<String>,
+        options: RunOptions,
+    ) -> PierResult<RunOutcome> {
+        let alias = &self.resolve_alias(alias)?;
+        let script = self.fetch_script(alias)?;
+        let log = match options.log || script.log.unwrap_or(false) {
+            true => Some(RunLog::create(alias)?),
+            false => None,
         };
-        ensure!(!self.config.scripts.is_empty(), NoScriptsExists);
 
-        let mut table = Table::new();
-
-        table.set_format(*COOL_FORMAT);
-        // cyan titles
-        table.set_titles(row![
-            Fc -> "Alias",
-            Fc -> "Tags",
-            Fc -> "Command",
-            Fc -> "Description",
-        ]);
-
-        for (alias, script) in self.config.scripts.iter() {
-            let shbang = script.command.starts_with("#!");
-            let descp = match &script.description.as_ref() {
-                Some(d) => d,
-                None => "",
-            };
-
-            match (&tags, &script.tags) {
-                (Some(list_tags), Some(script_tags)) => {
-
-                    for tag in list_tags {
-                        if script_tags.contains(tag) {
-
-                            if shbang {
-                                table.add_row(row![
-                                    FY -> &alias,
-                                    Fg -> script_tags.join(","),
-                                    Fm -> "#! script",
-                                    Fw -> descp,
-                                ]);
-                            } else {
-                                table.add_row(row![
-                                    FY -> &alias,
-                                    Fg -> script_tags.join(","),
-                                    Fb -> script.display_command(cmd_full, width),
-                                    Fw -> descp,
-                                ]);
-                            }
-
-                            continue;
-                        }
-                    }
-                }
-                (None, Some(script_tags)) => {
-                    if shbang {
-                        table.add_row(row![
-                            FY -> &alias,
-                            Fg -> script_tags.join(","),
-                            Fm -> "#! script",
-                            Fw -> descp,
-                        ]);
-                    } else {
-                        table.add_row(row![
-                            FY -> &alias,
-                            Fg -> script_tags.join(","),
-                            Fb -> script.display_command(cmd_full, width),
-                            Fw -> descp,
-                        ]);
-                    }
+        self.run_script_with_log(alias, args, options, log)
+    }
 
-                    continue;
-                }
-                (None, None) => {
-                    if shbang {
-                        table.add_row(row![
-                            FY -> &alias,
-                            Fg -> "",
-                            Fm -> "#! script",
-                            Fw -> descp,
-                        ]);
-                    } else {
-                        table.add_row(row![
-                            FY -> &alias,
-                            Fg -> "",
-                            Fb -> script.display_command(cmd_full, width),
-                            Fw -> descp,
-                        ]);
-                    }
+    /// Runs a script like run_script, but captures the output in the given log if there is one.
+    pub fn run_script_with_log(
+        &self,
+        alias: &str,
+        args: Vec<String>,
+        options: RunOptions,
+        log: Option<RunLog>,
+    ) -> PierResult<RunOutcome> {
+        let alias = &self.resolve_alias(alias)?;
+        let timestamp = Local::now();
+        let started = Instant::now();
+        let result = self.run_with_hooks(alias, args.clone(), options, log.as_ref());
+
+        // The log is always finished, followers of the log wait for its end.
+        if let Some(log) = &log {
+            match &result {
+                Ok(outcome) => log.finish(&outcome.status)?,
+                Err(err) => log.fail(err)?,
+            }
+        }
 
-                    continue;
-                }
-                _ => (),
+        // Every run is recorded, also the ones a hook stopped or that couldn't start.
+        if self.config.default.history.unwrap_or(true) {
+            let record = RunRecord {
+                id: 0,
+                timestamp,
+                alias: alias.to_string(),
+                args,
+                config: fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone()),
+                cwd: env::current_dir().unwrap_or_default(),
+                exit_code: result.as_ref().ok().and_then(|outcome| exit_code(&outcome.status)),
+                duration_ms: started.elapsed().as_millis() as u64,
+                log: log.as_ref().map(|log| log.path.clone()),
             };
+            // A broken history shouldn't hide the result of the script that already ran.
+            if let Err(err) = History::open().and_then(|history| history.append(record)) {
+                self.emit(Event::HistoryFailed {
+                    error: err.to_string(),
+                });
+            }
         }
 
-        // forced color explicitly. works in pipes
-        table.print_tty(true);
-
-        Ok(())
+        result
     }
 
-    /// Runs a script and print stdout and stderr of the command.
-    pub fn run_script(&self, alias: &str, args: Vec<String>) -> PierResult<ExitStatus> {
+    /// Runs a script with its hooks around it.
+    fn run_with_hooks(
+        &self,
+        alias: &str,
+        args: Vec<String>,
+        options: RunOptions,
+        log: Option<&RunLog>,
+    ) -> PierResult<RunOutcome> {
+        let alias = &self.resolve_alias(alias)?;
         let script = self.fetch_script(alias)?;
-        let interpreter = match self.config.default.interpreter {
-            Some(ref interpreter) => interpreter.clone(),
-            None => fallback_shell(),
+        let hooks: Vec<&Hooks> = vec![self.config.default.hooks.as_ref(), script.hooks.as_ref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        let output = OutputTarget {
+            log,
+            passthrough: options.passthrough,
+        };
+        let started = Instant::now();
+        let mut outcome = Output {
+            status: ExitStatus::from_raw(0),
+            stdout: vec![],
+            stderr: vec![],
         };
 
-        if self.verbose {
-            println!("Starting script \"{}\"", alias);
-            println!("-------------------------");
+        let mut env = BTreeMap::new();
+        env.insert(String::from("PIER_ALIAS"), alias.to_string());
+        env.insert(String::from("PIER_ARGS"), args.join(" "));
+
+        for hook in hooks.iter().filter_map(|hooks| hooks.before.as_ref()) {
+            append_output(&mut outcome, self.run_hook(hook, &env, output)?);
+            if !outcome.status.success() {
+                return Ok(RunOutcome::new(outcome, started.elapsed()));
+            }
+        }
+
+        let script_started = Instant::now();
+        let result = self.run_with_dependencies(alias, args, options.parallel, output);
+        let duration = script_started.elapsed();
+        env.insert(
+            String::from("PIER_DURATION_MS"),
+            duration.as_millis().to_string(),
+        );
+        let status = match result {
+            Ok(script_output) => {
+                append_output(&mut outcome, script_output);
+                outcome.status
+            }
+            // The script couldn't run, which is a failure the hooks still clean up after.
+            Err(err) => {
+                env.insert(String::from("PIER_EXIT_CODE"), String::new());
+                self.run_finished_hooks(&hooks, false, &env, output)?;
+                return Err(err);
+            }
         };
 
-        let cmd = match script.has_shebang() {
-            true => script.run_with_shebang(args)?,
-            false => script.run_with_cli_interpreter(&interpreter, args)?,
+        env.insert(
+            String::from("PIER_EXIT_CODE"),
+            exit_code(&status)
+                .map(|code| code.to_string())
+                .unwrap_or_default(),
+        );
+
+        append_output(
+            &mut outcome,
+            self.run_finished_hooks(&hooks, status.success(), &env, output)?,
+        );
+        // The hooks that run after the script don't change its exit status.
+        outcome.status = status;
+
+        Ok(RunOutcome::new(outcome, started.elapsed()))
+    }
+
+    /// Runs the hooks for a finished script and returns their combined output.
+    fn run_finished_hooks(
+        &self,
+        hooks: &[&Hooks],
+        success: bool,
+        env: &BTreeMap<String, String>,
+        output: OutputTarget,
+    ) -> PierResult<Output> {
+        let mut combined = Output {
+            status: ExitStatus::from_raw(0),
+            stdout: vec![],
+            stderr: vec![],
         };
+        // The hooks of the script run before the default hooks once the script has finished.
+        for hooks in hooks.iter().rev() {
+            let outcome_hook = match success {
+                true => &hooks.on_success,
+                false => &hooks.on_failure,
+            };
+            for hook in outcome_hook.iter().chain(hooks.after.iter()) {
+                append_output(&mut combined, self.run_hook(hook, env, output)?);
+            }
+        }
+        Ok(combined)
+    }
 
-        let stdout = String::from_utf8_lossy(&cmd.stdout);
-        let stderr = String::from_utf8_lossy(&cmd.stderr);
+    /// Runs a hook with the default interpreter.
+    fn run_hook(
+        &self,
+        hook: &str,
+        env: &BTreeMap<String, String>,
+        output: OutputTarget,
+    ) -> PierResult<Output> {
+        Hooks::run(hook, &self.interpreter(), env, output)
+    }
 
-        if stdout.len() > 0 {
-            println!("{}", stdout);
+    /// The interpreter used for scripts without a shebang.
+    fn interpreter(&self) -> Vec<String> {
+        match self.config.default.interpreter {
+            Some(ref interpreter) => interpreter.clone(),
+            None => fallback_shell(),
+        }
+    }
+
+    /// Runs a script after its prerequisites and returns the output of every script that ran,
+    /// with the exit status of the first failing script or the status of the script itself.
+    ///
+    /// Prerequisites in the same layer of the dependency graph run in parallel when asked to.
+    fn run_with_dependencies(
+        &self,
+        alias: &str,
+        args: Vec<String>,
+        parallel: bool,
+        output: OutputTarget,
+    ) -> PierResult<Output> {
+        let mut layers = execution_layers(&self.config.scripts, alias)?;
+        // The last layer only holds the requested script, which is the only one receiving args.
+        layers.pop();
+
+        let mut combined = Output {
+            status: ExitStatus::from_raw(0),
+            stdout: vec![],
+            stderr: vec![],
         };
-        if stderr.len() > 0 {
-            eprintln!("{}", stderr);
+
+        for layer in layers {
+            let outputs: Vec<PierResult<Output>> = if parallel && layer.len() > 1 {
+                thread::scope(|scope| {
+                    let handles: Vec<_> = layer
+                        .iter()
+                        .map(|dependency| {
+                            scope.spawn(move || self.run_single(dependency, vec![], output))
+                        })
+                        .collect();
+
+                    handles
+                        .into_iter()
+                        .map(|handle| handle.join().expect("Prerequisite thread panicked"))
+                        .collect()
+                })
+            } else {
+                let mut outputs = vec![];
+                for dependency in &layer {
+                    let cmd = self.run_single(dependency, vec![], output)?;
+                    let failed = !cmd.status.success();
+                    outputs.push(Ok(cmd));
+                    if failed {
+                        break;
+                    }
+                }
+                outputs
+            };
+
+            let mut failed = None;
+            for cmd in outputs {
+                let cmd = cmd?;
+                if !cmd.status.success() && failed.is_none() {
+                    failed = Some(cmd.status);
+                }
+                append_output(&mut combined, cmd);
+            }
+            if let Some(status) = failed {
+                combined.status = status;
+                return Ok(combined);
+            }
+        }
+
+        append_output(&mut combined, self.run_single(alias, args, output)?);
+
+        Ok(combined)
+    }
+
+    /// Runs a single script and returns its output.
+    fn run_single(
+        &self,
+        alias: &str,
+        args: Vec<String>,
+        output: OutputTarget,
+    ) -> PierResult<Output> {
+        let script = self.fetch_script(alias)?;
+        let interpreter = self.interpreter();
+
+        self.emit(Event::ScriptStarted {
+            alias: alias.to_string(),
+        });
+        if let Some(log) = output.log {
+            log.write_line("pier", &format!("Starting script \"{}\"", alias))?;
+        }
+
+        let cmd = match &script.steps {
+            Some(steps) => {
+                let cwd = script.cwd.as_deref();
+                self.run_steps(alias, steps, &interpreter, cwd, &args, output)?
+            }
+            None if script.runs_reference() => script.run_reference(args, output)?,
+            None => {
+                match script.has_shebang() {
+                    true => script.run_with_shebang(args, output)?,
+                    false => script.run_with_cli_interpreter(&interpreter, args, output)?,
+                }
+            }
         };
 
-        if self.verbose {
-            println!("-------------------------");
-            println!("Script complete");
+        self.emit(Event::ScriptFinished {
+            alias: alias.to_string(),
+        });
+
+        Ok(cmd)
+    }
+
+    /// Runs the steps of a script in order and stops at the first failing step unless that step
+    /// allows errors.
+    fn run_steps(
+        &self,
+        alias: &str,
+        steps: &[Step],
+        interpreter: &[String],
+        cwd: Option<&Path>,
+        args: &[String],
+        output: OutputTarget,
+    ) -> PierResult<Output> {
+        let mut combined = Output {
+            status: ExitStatus::from_raw(0),
+            stdout: vec![],
+            stderr: vec![],
         };
 
-        Ok(cmd.status)
+        for (index, step) in steps.iter().enumerate() {
+            let command = step.command.lines().next().unwrap_or_default();
+            if let Some(log) = output.log {
+                let header = format!("Step {}/{}: {}", index + 1, steps.len(), command);
+                log.write_line("pier", &header)?;
+            }
+            self.emit(Event::StepStarted {
+                alias: alias.to_string(),
+                step: index + 1,
+                steps: steps.len(),
+                command: command.to_string(),
+            });
+
+            let cmd = step.run(alias, interpreter, cwd, args, output)?;
+            let failed = !cmd.status.success() && !step.continue_on_error;
+            append_output(&mut combined, cmd);
+
+            if failed {
+                return Ok(combined);
+            }
+            combined.status = ExitStatus::from_raw(0);
+        }
+
+        Ok(combined)
+    }
+}
+
+/// The store of a config file or a directory with a file for every script.
+fn open_store(path: PathBuf) -> Box<dyn ScriptStore> {
+    match path.is_dir() {
+        true => Box::new(DirectoryStore::new(path)),
+        false => Box::new(TomlStore::new(path)),
     }
 }
 
+/// The exit code of a script, 128 plus the signal when a signal ended it like in a shell.
+pub fn exit_code(status: &ExitStatus) -> Option<i32> {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+}
+
+/// Adds the output of a command to the output collected so far, the status becomes the status of
+/// the command.
+fn append_output(combined: &mut Output, cmd: Output) {
+    combined.status = cmd.status;
+    combined.stdout.extend(cmd.stdout);
+    combined.stderr.extend(cmd.stderr);
+}
+
 pub fn open_editor(content: Option<&str>) -> PierResult<String> {
     let edited_text = scrawl::editor::new()
-        .contents(match content {
-            Some(txt) => txt,
-            None => "",
-        })
+        .contents(content.unwrap_or_default())
         .open()
         .context(EditorError)?;
 
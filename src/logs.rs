@@ -0,0 +1,252 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, prelude::*, BufReader, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use chrono::Local;
+use snafu::{OptionExt, ResultExt};
+
+use super::defaults::*;
+use super::error::*;
+use super::PierResult;
+
+/// The last line of every finished log starts with this marker.
+const LOG_END_MARKER: &str = "[pier] Script exited";
+
+/// A log file which captures the output of a single script run.
+#[derive(Debug)]
+pub struct RunLog {
+    pub path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl RunLog {
+    /// Creates a new log file for the alias and removes the oldest logs of the alias which are
+    /// above the retention limit.
+    pub fn create(alias: &str) -> PierResult<Self> {
+        let dir = log_dir(alias)?;
+        fs::create_dir_all(&dir).context(CreateDirectory)?;
+
+        let path = dir.join(format!("{}.log", Local::now().format("%Y%m%dT%H%M%S%.6f")));
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context(LogWrite { path: &path })?;
+
+        let mut logs = log_files(alias)?;
+        if logs.len() > LOG_RETENTION {
+            for old_log in logs.drain(..logs.len() - LOG_RETENTION) {
+                // Another process might have removed the log already.
+                let _ = fs::remove_file(old_log);
+            }
+        }
+
+        Ok(RunLog {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Writes a timestamped line from the given stream to the log.
+    pub fn write_line(&self, stream: &str, line: &str) -> PierResult<()> {
+        self.write_bytes(stream, line.as_bytes())
+    }
+
+    /// Writes a timestamped line of raw output, which doesn't have to be UTF-8, to the log.
+    fn write_bytes(&self, stream: &str, line: &[u8]) -> PierResult<()> {
+        let mut file = self.file.lock().expect("Log file lock poisoned");
+        let prefix = format!("{} [{}] ", Local::now().format("%Y-%m-%d %H:%M:%S%.3f"), stream);
+        file.write_all(prefix.as_bytes())
+            .and_then(|_| file.write_all(line))
+            .and_then(|_| file.write_all(b"\n"))
+            .context(LogWrite { path: &self.path })?;
+
+        Ok(())
+    }
+
+    /// Marks the end of the run in the log.
+    pub fn finish(&self, status: &ExitStatus) -> PierResult<()> {
+        let code = match status.code() {
+            Some(code) => code.to_string(),
+            None => String::from("signal"),
+        };
+        let mut file = self.file.lock().expect("Log file lock poisoned");
+        writeln!(file, "{} with {}", LOG_END_MARKER, code)
+            .context(LogWrite { path: &self.path })?;
+
+        Ok(())
+    }
+
+    /// Marks the end of a run that failed before the script could finish.
+    pub fn fail(&self, error: &PierError) -> PierResult<()> {
+        let mut file = self.file.lock().expect("Log file lock poisoned");
+        writeln!(file, "{} with {}", LOG_END_MARKER, error)
+            .context(LogWrite { path: &self.path })?;
+
+        Ok(())
+    }
+
+    /// Runs the command while copying its stdout and stderr to the log. The output is also passed
+    /// through to the terminal when asked to, otherwise it is captured in the returned output.
+    pub fn tee(&self, command: &mut Command, passthrough: bool) -> PierResult<Output> {
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context(CommandExec)?;
+
+        let stdout = child.stdout.take().expect("Child stdout is piped");
+        let stderr = child.stderr.take().expect("Child stderr is piped");
+
+        let (stdout, stderr) = thread::scope(|scope| -> PierResult<(Vec<u8>, Vec<u8>)> {
+            let out = scope.spawn(|| self.copy_output(stdout, "stdout", passthrough, io::stdout()));
+            let err = scope.spawn(|| self.copy_output(stderr, "stderr", passthrough, io::stderr()));
+            Ok((
+                out.join().expect("Output thread panicked")?,
+                err.join().expect("Output thread panicked")?,
+            ))
+        })?;
+
+        let status = child.wait().context(CommandExec)?;
+
+        Ok(Output {
+            status,
+            stdout,
+            stderr,
+        })
+    }
+
+    /// Writes every line to the log and either passes the output through to the terminal as it
+    /// comes, prompts without a newline included, or returns it. The output is copied as it is,
+    /// it doesn't have to be UTF-8.
+    fn copy_output<R: Read, W: Write>(
+        &self,
+        mut reader: R,
+        stream: &str,
+        passthrough: bool,
+        mut terminal: W,
+    ) -> PierResult<Vec<u8>> {
+        let mut captured = vec![];
+        let mut line = vec![];
+        let mut buffer = [0; 8192];
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err).context(CommandExec),
+            };
+            let chunk = &buffer[..read];
+
+            match passthrough {
+                true => terminal
+                    .write_all(chunk)
+                    .and_then(|_| terminal.flush())
+                    .context(CommandExec)?,
+                false => captured.extend_from_slice(chunk),
+            }
+
+            for byte in chunk {
+                match byte {
+                    b'\n' => {
+                        self.write_bytes(stream, &line)?;
+                        line.clear();
+                    }
+                    byte => line.push(*byte),
+                }
+            }
+        }
+        // The last line of the output doesn't need a newline.
+        if !line.is_empty() {
+            self.write_bytes(stream, &line)?;
+        }
+
+        Ok(captured)
+    }
+}
+
+/// Marks the end of a log whose run was killed by a signal.
+pub fn mark_killed(path: &Path, signal: i32) -> PierResult<()> {
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(path)
+        .context(LogWrite { path })?;
+    writeln!(file, "{} with signal {}", LOG_END_MARKER, signal).context(LogWrite { path })?;
+
+    Ok(())
+}
+
+/// The directory which holds the logs of an alias.
+pub fn log_dir(alias: &str) -> PierResult<PathBuf> {
+    Ok(state_dir()?.join(LOGS_DIR).join(alias))
+}
+
+/// The log files of an alias from oldest to newest.
+pub fn log_files(alias: &str) -> PierResult<Vec<PathBuf>> {
+    let dir = log_dir(alias)?;
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut logs: Vec<PathBuf> = fs::read_dir(&dir)
+        .context(LogRead { path: &dir })?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "log"))
+        .collect();
+    // The file names are timestamps so they sort chronologically.
+    logs.sort();
+
+    Ok(logs)
+}
+
+/// The most recent log file of an alias.
+pub fn latest_log(alias: &str) -> PierResult<PathBuf> {
+    let log = log_files(alias)?.pop().context(LogNotFound {
+        alias: &alias.to_string(),
+    })?;
+
+    Ok(log)
+}
+
+/// Prints a log file. When following, it keeps printing new lines until the run has finished.
+pub fn print_log(path: &Path, follow: bool) -> PierResult<()> {
+    let file = File::open(path).context(LogRead { path })?;
+    let mut reader = BufReader::new(file);
+    let stdout = io::stdout();
+    // Scripts can write output that isn't UTF-8, the log keeps it as it is.
+    let mut line = vec![];
+
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line).context(LogRead { path })?;
+
+        if read > 0 && line.ends_with(b"\n") {
+            stdout
+                .lock()
+                .write_all(&line)
+                .context(LogRead { path })?;
+            if line.starts_with(LOG_END_MARKER.as_bytes()) {
+                return Ok(());
+            }
+            continue;
+        }
+
+        if !follow {
+            stdout
+                .lock()
+                .write_all(&line)
+                .context(LogRead { path })?;
+            return Ok(());
+        }
+
+        // Wait for a complete line to be written and read it again from its start.
+        reader
+            .seek(SeekFrom::Current(-(read as i64)))
+            .context(LogRead { path })?;
+        thread::sleep(Duration::from_millis(LOG_FOLLOW_INTERVAL_MS));
+    }
+}
@@ -2,7 +2,7 @@
 #[macro_export]
 macro_rules! pier_err {
     ($type:expr) => {
-        return Err($type)?;
+        return Err($type)?
     };
 }
 
@@ -17,6 +17,20 @@ macro_rules! xdg_config_home {
     };
 }
 
+/// macro to return an absolute path base on the relative_path from $XDG_STATE_HOME, which falls
+/// back to $HOME/.local/state
+#[macro_export]
+macro_rules! xdg_state_home {
+    ($relative_path:expr) => {
+        match std::env::var_os("XDG_STATE_HOME") {
+            Some(base) if !base.is_empty() => {
+                Some(std::path::PathBuf::from(base).join($relative_path))
+            }
+            _ => home!(".local/state").map(|base| base.join($relative_path)),
+        }
+    };
+}
+
 /// macro to return an absolute path base on the relative_path from $HOME
 #[macro_export]
 macro_rules! home {
@@ -28,7 +42,6 @@ macro_rules! home {
     };
 }
 
-///
 #[macro_export]
 macro_rules! clap_arg {
     ($relative_path:expr) => {
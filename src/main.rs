@@ -1,11 +1,41 @@
+use std::fs;
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
 use std::process;
 use structopt::StructOpt;
 
+#[macro_use]
+extern crate lazy_static;
+
+mod render;
+
 use pier::{
-    cli::{Cli, CliSubcommand},
+    build::build_script,
+    bundle::{default_files_dir, Bundle, Conflict, ImportAction},
+    cli::{
+        Cli, CliSubcommand, ExportSubcommand, ImportSubcommand, ScheduleSubcommand,
+        SourcesSubcommand,
+    },
+    config::in_group,
+    docs::{render_docs, DocsFormat},
+    error::PierError,
+    export::shell_functions,
+    history::History,
+    import::{
+        last_command, rank_history, scan_dir, scan_tasks, shell_history, suggest_alias, Shell,
+        TaskSource,
+    },
+    jobs::Jobs,
+    logs::{latest_log, print_log},
     open_editor,
+    rpc::rpc,
+    schedule::{daemon, scheduled_scripts, ScheduleState},
     script::Script,
-    Pier, PierResult,
+    serve::{serve, ServeOpts},
+    shims::{self, default_bin_dir},
+    usage::{usage_by_alias, SortOrder},
+    watch::WatchOpts,
+    exit_code, Pier, PierResult, RunOptions,
 };
 
 fn main() {
@@ -14,7 +44,7 @@ fn main() {
     match handle_subcommands(opt) {
         Ok(status) => {
             if let Some(status) = status {
-                let code = status.code().unwrap_or(0);
+                let code = exit_code(&status).unwrap_or(0);
                 process::exit(code)
             } else {
                 process::exit(0)
@@ -36,83 +66,604 @@ fn handle_subcommands(cli: Cli) -> PierResult<Option<process::ExitStatus>> {
                 alias,
                 description,
                 tags,
+                depends_on,
+                reference,
+                last,
                 force,
             } => {
-                let mut pier = Pier::from(cli.opts.path, cli.opts.verbose)?;
+                let mut pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                let command = match (command, &reference) {
+                    (Some(cmd), _) => cmd,
+                    _ if last => last_command(&shell_history(None, None)?)?,
+                    (None, Some(_)) => String::new(),
+                    (None, None) => open_editor(None)?,
+                };
                 pier.add_script(Script {
-                    alias,
+                    alias: alias.clone(),
                     description,
-                    command: match command {
-                        Some(cmd) => cmd,
-                        None => open_editor(None)?,
-                    },
+                    command,
                     tags,
-                    reference: None,
+                    depends_on,
+                    reference,
+                    ..Script::default()
                 }, force)?;
                 pier.write()?;
+                println!("Added {}", alias);
             }
 
             CliSubcommand::Edit { alias } => {
-                let mut pier = Pier::from(cli.opts.path, cli.opts.verbose)?;
-                pier.edit_script(&alias)?;
+                let mut pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                let command = open_editor(Some(&pier.fetch_script(&alias)?.command))?;
+                pier.edit_script(&alias, command)?;
                 pier.write()?;
+                println!("Edited {}", alias);
             }
             CliSubcommand::Remove { alias } => {
-                let mut pier = Pier::from(cli.opts.path, cli.opts.verbose)?;
-                pier.remove_script(&alias)?;
+                let mut pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                let script = pier.remove_script(&alias)?;
                 pier.write()?;
+                // Installed shims would only fail to find the script.
+                shims::uninstall(pier.path(), Some(&[script.alias]), None)?;
+                println!("Removed {}", alias);
             }
             CliSubcommand::ConfigInit => {
                 let mut pier = Pier::new();
                 pier.config_init(cli.opts.path)?;
+                println!("Created {}", pier.path().display());
             }
             CliSubcommand::Show { alias } => {
-                let pier = Pier::from(cli.opts.path, cli.opts.verbose)?;
+                let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
                 let script = pier.fetch_script(&alias)?;
-                println!("{}", script.command);
+                match &script.steps {
+                    Some(steps) => {
+                        for (index, step) in steps.iter().enumerate() {
+                            println!("# Step {}\n{}", index + 1, step.command);
+                        }
+                    }
+                    None if script.runs_reference() => print!("{}", script.read_reference()?),
+                    None => println!("{}", script.command),
+                }
             }
             CliSubcommand::List {
                 list_aliases,
                 tags,
                 cmd_full,
                 cmd_width,
+                sort,
+                tree,
+                group,
             } => {
-                let pier = Pier::from(cli.opts.path, cli.opts.verbose)?;
+                let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                let mut scripts = pier.list_scripts(tags, sort)?;
+                if let Some(group) = group {
+                    scripts.retain(|script| in_group(&script.alias, &group));
+                    if scripts.is_empty() {
+                        return Err(PierError::GroupNotFound { group });
+                    }
+                }
                 if list_aliases {
-                    pier.list_aliases(tags)?
+                    render::list_aliases(&scripts)
+                } else if tree {
+                    render::list_tree(&scripts)
                 } else {
-                    pier.list_scripts(tags, cmd_full, cmd_width)?
+                    render::list_scripts(&scripts, cmd_full, pier.command_width(cmd_width))?
                 }
             }
-            CliSubcommand::Run { alias, args } => {
-                let pier = Pier::from(cli.opts.path, cli.opts.verbose)?;
-                let exit_code = pier.run_script(&alias, args)?;
-                return Ok(Some(exit_code));
+            CliSubcommand::Run {
+                alias,
+                args,
+                parallel,
+                log,
+                detach,
+            } => {
+                let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                if detach {
+                    let job = pier.detach_script(&alias, args, parallel)?;
+                    println!("Started job {} for {}", job.id, job.alias);
+                    return Ok(None);
+                }
+                let options = RunOptions {
+                    parallel,
+                    log,
+                    passthrough: true,
+                };
+                let outcome = pier.run_script(&alias, args, options)?;
+                return Ok(Some(outcome.status));
             }
             CliSubcommand::Copy {
                 from_alias,
                 to_alias,
             } => {
-                let mut pier = Pier::from(cli.opts.path, cli.opts.verbose)?;
+                let mut pier = open_pier(cli.opts.path, cli.opts.verbose)?;
                 pier.copy_script(&from_alias, &to_alias)?;
                 pier.write()?;
+                println!("Copy from alias {} to new alias {}", from_alias, to_alias);
             }
             CliSubcommand::Move {
                 from_alias,
                 to_alias,
                 force,
             } => {
-                let mut pier = Pier::from(cli.opts.path, cli.opts.verbose)?;
-                pier.move_script(&from_alias, &to_alias, force)?;
+                let mut pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                let resolved_alias = pier.resolve_alias(&from_alias)?;
+                pier.move_script(&resolved_alias, &to_alias, force)?;
                 pier.write()?;
+                // Installed shims follow the script to its new alias.
+                shims::rename(pier.path(), &resolved_alias, &to_alias)?;
+                println!("Move from alias {} to new alias {}", from_alias, to_alias);
+            }
+            CliSubcommand::Runs {
+                alias,
+                failed,
+                limit,
+                rerun,
+            } => {
+                let alias = match alias {
+                    Some(alias) => Some(recorded_alias(cli.opts.path, cli.opts.verbose, alias)?),
+                    None => None,
+                };
+                let history = History::open()?;
+                if let Some(id) = rerun {
+                    let options = RunOptions {
+                        passthrough: true,
+                        ..RunOptions::default()
+                    };
+                    let outcome = history.get(id)?.rerun(cli.opts.verbose, options)?;
+                    return Ok(Some(outcome.status));
+                }
+
+                let mut records: Vec<_> = history
+                    .records()?
+                    .into_iter()
+                    .filter(|record| alias.as_ref().map_or(true, |alias| &record.alias == alias))
+                    .filter(|record| !failed || record.exit_code != Some(0))
+                    .collect();
+                if let Some(limit) = limit {
+                    records = records.split_off(records.len().saturating_sub(limit));
+                }
+                render::list_runs(&records)?;
+            }
+            CliSubcommand::Logs { alias, follow, run } => {
+                let alias = recorded_alias(cli.opts.path, cli.opts.verbose, alias)?;
+                let path = match run {
+                    Some(id) => History::open()?.get(id)?.log_of(&alias)?,
+                    None => latest_log(&alias)?,
+                };
+                print_log(&path, follow)?;
+            }
+            CliSubcommand::Watch {
+                alias,
+                args,
+                paths,
+                globs,
+                ignore,
+                clear,
+                debounce_ms,
+                no_gitignore,
+            } => {
+                let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                pier.watch_script(
+                    &alias,
+                    args,
+                    WatchOpts {
+                        paths,
+                        globs,
+                        ignore,
+                        clear: if clear { Some(true) } else { None },
+                        debounce_ms,
+                        gitignore: if no_gitignore { Some(false) } else { None },
+                    },
+                )?;
+            }
+            CliSubcommand::Daemon { once } => {
+                let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                daemon(pier, once)?;
+            }
+            CliSubcommand::Serve {
+                listen,
+                socket,
+                token,
+                allow,
+            } => {
+                let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                serve(
+                    &pier,
+                    &ServeOpts {
+                        listen,
+                        socket,
+                        token,
+                        allow,
+                    },
+                )?;
+            }
+            CliSubcommand::Rpc => {
+                let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                rpc(pier)?;
+            }
+            CliSubcommand::Import { cmd } => {
+                let mut pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                match cmd {
+                    ImportSubcommand::Dir {
+                        path,
+                        inline,
+                        yes,
+                        force,
+                    } => import_scripts(&mut pier, scan_dir(&path, inline)?, yes, force)?,
+                    ImportSubcommand::Make {
+                        file,
+                        prefix,
+                        yes,
+                        force,
+                    } => import_tasks(&mut pier, TaskSource::Make, file, prefix, yes, force)?,
+                    ImportSubcommand::Just {
+                        file,
+                        prefix,
+                        yes,
+                        force,
+                    } => import_tasks(&mut pier, TaskSource::Just, file, prefix, yes, force)?,
+                    ImportSubcommand::Npm {
+                        file,
+                        prefix,
+                        yes,
+                        force,
+                    } => import_tasks(&mut pier, TaskSource::Npm, file, prefix, yes, force)?,
+                    ImportSubcommand::History {
+                        shell,
+                        file,
+                        limit,
+                        yes,
+                        force,
+                    } => {
+                        let history = shell_history(shell, file)?;
+                        let mut scripts: Vec<Script> = vec![];
+                        for entry in rank_history(&history).into_iter().take(limit) {
+                            // Suggested aliases are unique, among the new scripts as well.
+                            let base = suggest_alias(&entry.command);
+                            let alias = (1..)
+                                .map(|n| match n {
+                                    1 => base.clone(),
+                                    n => format!("{}-{}", base, n),
+                                })
+                                .find(|alias| {
+                                    !pier.config().scripts.contains_key(alias)
+                                        && scripts.iter().all(|script| &script.alias != alias)
+                                })
+                                .expect("There is always a free alias");
+                            scripts.push(Script {
+                                alias,
+                                command: entry.command,
+                                ..Script::default()
+                            });
+                        }
+                        import_scripts(&mut pier, scripts, yes, force)?;
+                    }
+                    ImportSubcommand::Bundle {
+                        file,
+                        conflict,
+                        dry_run,
+                        files_dir,
+                    } => {
+                        let files_dir = match files_dir {
+                            Some(files_dir) => files_dir,
+                            None => default_files_dir()?,
+                        };
+                        import_bundle(&mut pier, &file, conflict, dry_run, &files_dir)?;
+                    }
+                }
+            }
+            CliSubcommand::Install {
+                alias,
+                all,
+                bin_dir,
+            } => {
+                let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                let aliases = match all {
+                    true => pier
+                        .list_scripts(None, SortOrder::Name)?
+                        .iter()
+                        .map(|script| script.alias.clone())
+                        .collect(),
+                    false => vec![alias.expect("Alias is required unless --all.")],
+                };
+                let bin_dir = match bin_dir {
+                    Some(bin_dir) => bin_dir,
+                    None => default_bin_dir()?,
+                };
+                for shim in shims::install(&pier, &aliases, &bin_dir)? {
+                    println!("Installed {} as {}", shim.alias, shim.path.display());
+                }
+            }
+            CliSubcommand::Uninstall {
+                alias,
+                all,
+                bin_dir,
+            } => {
+                let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                // Without a list of aliases every shim of the config is removed.
+                let aliases = match all {
+                    true => None,
+                    false => {
+                        let alias = alias.expect("Alias is required unless --all.");
+                        Some(vec![pier.resolve_recorded_alias(&alias)?])
+                    }
+                };
+                let removed =
+                    shims::uninstall(pier.path(), aliases.as_deref(), bin_dir.as_deref())?;
+                if removed.is_empty() {
+                    println!("No installed scripts found");
+                }
+                for shim in removed {
+                    println!("Removed {}", shim.path.display());
+                }
+            }
+            CliSubcommand::Build {
+                alias,
+                output,
+                force,
+            } => {
+                let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                let output = output.unwrap_or_else(|| PathBuf::from(&alias));
+                build_script(&pier, &alias, &output, force)?;
+                println!("Created {}", output.display());
+            }
+            CliSubcommand::Docs {
+                format,
+                output,
+                tags,
+            } => {
+                let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                match output {
+                    Some(path) => {
+                        let format = format.unwrap_or_else(|| DocsFormat::of_file(&path));
+                        let docs = render_docs(&pier, format, tags)?;
+                        fs::write(&path, docs).map_err(|source| PierError::DocsWrite {
+                            source,
+                            path: path.clone(),
+                        })?;
+                        println!("Created {}", path.display());
+                    }
+                    None => {
+                        let format = format.unwrap_or(DocsFormat::Markdown);
+                        print!("{}", render_docs(&pier, format, tags)?);
+                    }
+                }
+            }
+            CliSubcommand::Export { cmd } => match cmd {
+                ExportSubcommand::Shell {
+                    format,
+                    tags,
+                    inline,
+                } => {
+                    let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                    let shell = match format {
+                        Some(shell) => shell,
+                        None => Shell::current()?,
+                    };
+                    print!("{}", shell_functions(&pier, shell, tags, inline)?);
+                }
+                ExportSubcommand::Bundle { tags, output } => {
+                    let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                    let bundle = Bundle::export(&pier, tags)?.to_toml()?;
+                    match output {
+                        Some(path) => {
+                            fs::write(&path, bundle).map_err(|source| PierError::BundleWrite {
+                                source,
+                                path: path.clone(),
+                            })?;
+                            println!("Created {}", path.display());
+                        }
+                        None => print!("{}", bundle),
+                    }
+                }
+            },
+            CliSubcommand::Sources { cmd } => match cmd {
+                SourcesSubcommand::Update => {
+                    let sources = Pier::read_sources(cli.opts.path)?;
+                    if sources.is_empty() {
+                        println!("No sources found");
+                    }
+                    for source in &sources {
+                        println!("Updated {} to {}", source.git, source.update()?);
+                    }
+                }
+            },
+            CliSubcommand::Schedule { cmd } => match cmd {
+                ScheduleSubcommand::List => {
+                    let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                    let scripts = scheduled_scripts(&pier)?;
+                    render::list_schedules(&scripts, &ScheduleState::open()?.last_fires()?)?;
+                }
+            },
+            CliSubcommand::Jobs => {
+                render::list_jobs(&Jobs::open()?.list()?)?;
+            }
+            CliSubcommand::Attach { job } => {
+                Jobs::open()?.attach(job)?;
+            }
+            CliSubcommand::Kill { job, signal } => {
+                let job = Jobs::open()?.kill(job, signal)?;
+                println!("Killed job {} for {}", job.id, job.alias);
+            }
+            CliSubcommand::Supervise { job } => {
+                Jobs::open()?.supervise(job, cli.opts.verbose)?;
+            }
+            CliSubcommand::Stats { limit } => {
+                let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+                let scripts = pier.list_scripts(None, SortOrder::Count)?;
+                let usages = usage_by_alias(&History::open()?.records()?);
+                render::print_stats(&scripts, &usages, limit)?;
             }
         };
     } else {
         let alias = &cli.alias.expect("Alias is required unless subcommand.");
-        let pier = Pier::from(cli.opts.path, cli.opts.verbose)?;
-        let exit_code = pier.run_script(alias, cli.args)?;
-        return Ok(Some(exit_code));
+        let pier = open_pier(cli.opts.path, cli.opts.verbose)?;
+        let options = RunOptions {
+            passthrough: true,
+            ..RunOptions::default()
+        };
+        let outcome = pier.run_script(alias, cli.args, options)?;
+        return Ok(Some(outcome.status));
     }
 
     Ok(None)
 }
+
+/// Loads the config at the path, or the first existing default config, and reports its events.
+fn open_pier(path: Option<PathBuf>, verbose: bool) -> PierResult<Pier> {
+    let mut pier = Pier::from(path, verbose)?;
+    pier.on_event(move |event| render::event(event, verbose));
+
+    Ok(pier)
+}
+
+/// The full alias of a script for looking up its runs and logs. Without a config, or when the
+/// script doesn't exist anymore, the alias is used as it is.
+fn recorded_alias(path: Option<PathBuf>, verbose: bool, alias: String) -> PierResult<String> {
+    match open_pier(path, verbose) {
+        Ok(pier) => pier.resolve_recorded_alias(&alias),
+        Err(_) => Ok(alias),
+    }
+}
+
+/// Imports the scripts of a bundle. Scripts with an alias that exists are handled as the conflict
+/// says, or asked about for each one without it. A dry run only shows what would change.
+fn import_bundle(
+    pier: &mut Pier,
+    file: &Path,
+    conflict: Option<Conflict>,
+    dry_run: bool,
+    files_dir: &Path,
+) -> PierResult<()> {
+    let bundle = Bundle::read(file)?;
+    let mut plan = bundle.plan(pier, conflict);
+    if dry_run {
+        render::show_bundle_plan(&bundle, &plan, files_dir)?;
+        return Ok(());
+    }
+
+    let stdin = io::stdin();
+    let mut answers = stdin.lock().lines();
+    for index in 0..plan.len() {
+        if plan[index].1 != ImportAction::Ask {
+            continue;
+        }
+        let alias = plan[index].0.clone();
+        print!(
+            "{} already exists. [S]kip, [o]verwrite, [r]ename, [q]uit or another alias: ",
+            alias
+        );
+        let _ = io::stdout().flush();
+
+        let answer = match answers.next() {
+            Some(Ok(answer)) => answer.trim().to_string(),
+            _ => String::from("q"),
+        };
+        plan[index].1 = match answer.to_lowercase().as_str() {
+            "" | "s" | "skip" => ImportAction::Skip,
+            "o" | "overwrite" => ImportAction::Overwrite,
+            "r" | "rename" => ImportAction::Rename(bundle.free_alias(pier, &alias, &plan)),
+            "q" | "quit" => {
+                println!("Imported 0 scripts");
+                return Ok(());
+            }
+            _ => {
+                let renamed = ImportAction::Rename(answer.clone());
+                let taken = pier.config().scripts.contains_key(&answer)
+                    || bundle.scripts.contains_key(&answer)
+                    || plan.iter().any(|(_, action)| action == &renamed);
+                match taken {
+                    true => ImportAction::Skip,
+                    false => ImportAction::Rename(answer),
+                }
+            }
+        };
+    }
+
+    let imported = bundle.import(pier, &plan, files_dir)?;
+    for (alias, action) in &plan {
+        match action {
+            ImportAction::Skip => println!("Skipped {}, the alias already exists", alias),
+            ImportAction::Rename(new_alias) => println!("Added {} as {}", alias, new_alias),
+            _ => (),
+        }
+    }
+    if imported > 0 {
+        pier.write()?;
+    }
+    println!("Imported {} scripts", imported);
+
+    Ok(())
+}
+
+/// Imports the tasks of a project file, prefixed by the name of the project directory unless
+/// another prefix is given.
+fn import_tasks(
+    pier: &mut Pier,
+    source: TaskSource,
+    file: Option<PathBuf>,
+    prefix: Option<String>,
+    yes: bool,
+    force: bool,
+) -> PierResult<()> {
+    let file = file.unwrap_or_else(|| source.default_file());
+    let prefix = prefix.or_else(|| {
+        let path = fs::canonicalize(&file).ok()?;
+        let project = path.parent()?.file_name()?;
+        Some(project.to_string_lossy().to_string())
+    });
+
+    import_scripts(pier, scan_tasks(source, &file, prefix.as_deref())?, yes, force)
+}
+
+/// Asks which of the scripts to add, unless all of them are accepted up front, and adds them to
+/// the config. Scripts with an alias that already exists are skipped unless forced.
+fn import_scripts(pier: &mut Pier, scripts: Vec<Script>, yes: bool, force: bool) -> PierResult<()> {
+    let stdin = io::stdin();
+    let mut answers = stdin.lock().lines();
+    let mut accept_all = yes;
+    let mut imported = 0;
+
+    for mut script in scripts {
+        if !force && pier.config().scripts.contains_key(&script.alias) {
+            println!("Skipped {}, the alias already exists", script.alias);
+            continue;
+        }
+
+        if !accept_all {
+            render::show_import(&script);
+            print!(
+                "Import {}? [Y]es, [n]o, [a]ll, [q]uit or another alias: ",
+                script.alias
+            );
+            let _ = io::stdout().flush();
+
+            let answer = match answers.next() {
+                Some(Ok(answer)) => answer.trim().to_string(),
+                _ => String::from("q"),
+            };
+            match answer.to_lowercase().as_str() {
+                "" | "y" | "yes" => (),
+                "a" | "all" => accept_all = true,
+                "n" | "no" => continue,
+                "q" | "quit" => break,
+                _ => {
+                    if !force && pier.config().scripts.contains_key(&answer) {
+                        println!("Skipped {}, the alias already exists", answer);
+                        continue;
+                    }
+                    script.alias = answer;
+                }
+            }
+        }
+
+        pier.add_script(script, force)?;
+        imported += 1;
+    }
+
+    if imported > 0 {
+        pier.write()?;
+    }
+    println!("Imported {} scripts", imported);
+
+    Ok(())
+}
@@ -0,0 +1,473 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+use prettytable::format::{FormatBuilder, LinePosition, LineSeparator, TableFormat};
+use prettytable::{row, Cell, Row, Table};
+
+use pier::{
+    bundle::{Bundle, ImportAction},
+    config::GROUP_SEPARATORS,
+    error::PierError,
+    events::Event,
+    history::RunRecord,
+    jobs::Job,
+    schedule::ScheduledScript,
+    script::Script,
+    usage::Usage,
+    PierResult,
+};
+
+lazy_static! {
+    static ref COOL_SEP: LineSeparator = LineSeparator::new('\u{2256}', '\u{2256}', '\u{2256}', '\u{2256}');
+
+    pub static ref COOL_FORMAT: TableFormat = FormatBuilder::new()
+      .column_separator('\u{22EE}')
+      .borders('\u{22EE}')
+      .separator(LinePosition::Title, *COOL_SEP)
+      .separator(LinePosition::Bottom, *COOL_SEP)
+      .separator(LinePosition::Top, *COOL_SEP)
+      .padding(1, 1)
+      .build();
+}
+
+/// Prints the aliases of the scripts, one per line.
+pub fn list_aliases(scripts: &[&Script]) {
+    for script in scripts {
+        println!("{}", script.alias);
+    }
+}
+
+/// A group or a script in the tree of the scripts, a script can be a group as well.
+struct TreeNode<'a> {
+    name: &'a str,
+    script: Option<&'a Script>,
+    children: Vec<TreeNode<'a>>,
+}
+
+impl<'a> TreeNode<'a> {
+    fn insert(nodes: &mut Vec<TreeNode<'a>>, parts: &[&'a str], script: &'a Script) {
+        let (name, rest) = match parts.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        let index = match nodes.iter().position(|node| node.name == *name) {
+            Some(index) => index,
+            None => {
+                nodes.push(TreeNode {
+                    name,
+                    script: None,
+                    children: vec![],
+                });
+                nodes.len() - 1
+            }
+        };
+        match rest.is_empty() {
+            true => nodes[index].script = Some(script),
+            false => TreeNode::insert(&mut nodes[index].children, rest, script),
+        }
+    }
+
+    fn print(&self, prefix: &str, connector: &str) {
+        let description = self.script.and_then(|script| script.description.as_deref());
+        match description {
+            Some(description) => println!("{}{}{}  {}", prefix, connector, self.name, description),
+            None => println!("{}{}{}", prefix, connector, self.name),
+        }
+
+        let prefix = match connector {
+            "├── " => format!("{}│   ", prefix),
+            "└── " => format!("{}    ", prefix),
+            _ => prefix.to_string(),
+        };
+        for (index, child) in self.children.iter().enumerate() {
+            let connector = match index + 1 == self.children.len() {
+                true => "└── ",
+                false => "├── ",
+            };
+            child.print(&prefix, connector);
+        }
+    }
+}
+
+/// Prints the scripts as a tree of their groups with their descriptions.
+pub fn list_tree(scripts: &[&Script]) {
+    let mut nodes = vec![];
+    for script in scripts {
+        let parts: Vec<&str> = script.alias.split_inclusive(GROUP_SEPARATORS).collect();
+        TreeNode::insert(&mut nodes, &parts, script);
+    }
+
+    for node in nodes {
+        node.print("", "");
+    }
+}
+
+/// Prints a terminal table of the scripts.
+pub fn list_scripts(scripts: &[&Script], cmd_full: bool, width: usize) -> PierResult<()> {
+    let mut table = Table::new();
+
+    table.set_format(*COOL_FORMAT);
+    // cyan titles
+    table.set_titles(row![
+        Fc -> "Alias",
+        Fc -> "Tags",
+        Fc -> "Command",
+        Fc -> "Description",
+    ]);
+
+    for script in scripts {
+        let tags = script
+            .tags
+            .as_ref()
+            .map(|tags| tags.join(","))
+            .unwrap_or_default();
+        let descp = script.description.as_deref().unwrap_or_default();
+
+        // References show the file they run, or that it's missing.
+        if script.runs_reference() {
+            let reference = script.reference.as_deref().unwrap_or_default();
+            match script.reference_path() {
+                Some(path) => {
+                    table.add_row(row![
+                        FY -> &script.alias,
+                        Fg -> tags,
+                        Fm -> format!("-> {}", path.display()),
+                        Fw -> descp,
+                    ]);
+                }
+                None => {
+                    table.add_row(row![
+                        FY -> &script.alias,
+                        Fg -> tags,
+                        Fr -> format!("missing: {}", reference),
+                        Fw -> descp,
+                    ]);
+                }
+            }
+            continue;
+        }
+
+        let label = match (&script.steps, script.command.starts_with("#!")) {
+            (Some(steps), _) => Some(format!("{} steps", steps.len())),
+            (None, true) => Some(String::from("#! script")),
+            (None, false) => None,
+        };
+
+        match label {
+            Some(label) => {
+                table.add_row(row![
+                    FY -> &script.alias,
+                    Fg -> tags,
+                    Fm -> label,
+                    Fw -> descp,
+                ]);
+            }
+            None => {
+                table.add_row(row![
+                    FY -> &script.alias,
+                    Fg -> tags,
+                    Fb -> script.display_command(cmd_full, width),
+                    Fw -> descp,
+                ]);
+            }
+        }
+    }
+
+    // forced color explicitly. works in pipes
+    print_table(&table)?;
+
+    Ok(())
+}
+
+/// Prints a script that is about to be imported.
+pub fn show_import(script: &Script) {
+    println!("{}", script.alias);
+    match &script.reference {
+        Some(reference) => println!("  reference:   {}", reference),
+        None => println!("  command:     {}", script.display_command(false, 60)),
+    }
+    if let Some(description) = &script.description {
+        println!("  description: {}", description);
+    }
+    if let Some(tags) = &script.tags {
+        println!("  tags:        {}", tags.join(","));
+    }
+}
+
+/// Prints what importing a bundle would do with every script and its referenced file.
+pub fn show_bundle_plan(
+    bundle: &Bundle,
+    plan: &[(String, ImportAction)],
+    files_dir: &Path,
+) -> PierResult<()> {
+    for (alias, action) in plan {
+        let new_alias = match action {
+            ImportAction::Add => {
+                println!("Would add {}", alias);
+                alias
+            }
+            ImportAction::Overwrite => {
+                println!("Would overwrite {}", alias);
+                alias
+            }
+            ImportAction::Rename(new_alias) => {
+                println!("Would add {} as {}", alias, new_alias);
+                new_alias
+            }
+            ImportAction::Skip => {
+                println!("Would skip {}, the alias already exists", alias);
+                continue;
+            }
+            ImportAction::Ask => {
+                println!("Would ask about {}, the alias already exists", alias);
+                continue;
+            }
+        };
+        if let Some(path) = bundle.file_path(alias, new_alias, files_dir)? {
+            println!("  and write {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the most used scripts and the scripts which were never run.
+pub fn print_stats(
+    scripts: &[&Script],
+    usages: &BTreeMap<String, Usage>,
+    limit: usize,
+) -> PierResult<()> {
+    let mut table = Table::new();
+
+    table.set_format(*COOL_FORMAT);
+    // cyan titles
+    table.set_titles(row![
+        Fc -> "Alias",
+        Fc -> "Runs",
+        Fc -> "Last run",
+        Fc -> "Frecency",
+    ]);
+
+    let used = scripts
+        .iter()
+        .filter_map(|script| usages.get(&script.alias).map(|usage| (&script.alias, usage)))
+        .take(limit);
+    for (alias, usage) in used {
+        let last_run = match usage.last_run {
+            Some(last_run) => last_run.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => String::new(),
+        };
+        table.add_row(row![
+            FY -> alias,
+            Fg -> usage.count,
+            Fw -> last_run,
+            Fw -> format!("{:.2}", usage.frecency),
+        ]);
+    }
+
+    println!("Most used scripts:");
+    // forced color explicitly. works in pipes
+    print_table(&table)?;
+
+    println!("\nNever used scripts:");
+    let mut never_used: Vec<&String> = scripts
+        .iter()
+        .map(|script| &script.alias)
+        .filter(|alias| !usages.contains_key(*alias))
+        .collect();
+    never_used.sort();
+    for alias in never_used {
+        println!("{}", alias);
+    }
+
+    Ok(())
+}
+
+/// Prints a terminal table of the runs.
+pub fn list_runs(records: &[RunRecord]) -> PierResult<()> {
+    let mut table = Table::new();
+
+    table.set_format(*COOL_FORMAT);
+    // cyan titles
+    table.set_titles(row![
+        Fc -> "Run",
+        Fc -> "Time",
+        Fc -> "Alias",
+        Fc -> "Args",
+        Fc -> "Exit",
+        Fc -> "Duration",
+        Fc -> "Cwd",
+    ]);
+
+    for record in records {
+        let exit_code = match record.exit_code {
+            Some(code) => code.to_string(),
+            None => String::from("error"),
+        };
+
+        let exit_style = match record.exit_code {
+            Some(0) => "Fg",
+            _ => "Fr",
+        };
+
+        table.add_row(Row::new(vec![
+            Cell::new(&record.id.to_string()).style_spec("FY"),
+            Cell::new(&record.timestamp.format("%Y-%m-%d %H:%M:%S").to_string()).style_spec("Fw"),
+            Cell::new(&record.alias).style_spec("FY"),
+            Cell::new(&record.args.join(" ")).style_spec("Fb"),
+            Cell::new(&exit_code).style_spec(exit_style),
+            Cell::new(&format!("{:.2}s", record.duration_ms as f64 / 1000.0)).style_spec("Fw"),
+            Cell::new(&record.cwd.display().to_string()).style_spec("Fw"),
+        ]));
+    }
+
+    // forced color explicitly. works in pipes
+    print_table(&table)?;
+
+    Ok(())
+}
+
+/// Prints a terminal table of the jobs.
+pub fn list_jobs(jobs: &[Job]) -> PierResult<()> {
+    let mut table = Table::new();
+
+    table.set_format(*COOL_FORMAT);
+    // cyan titles
+    table.set_titles(row![
+        Fc -> "Job",
+        Fc -> "Alias",
+        Fc -> "Args",
+        Fc -> "PID",
+        Fc -> "Status",
+        Fc -> "Started",
+        Fc -> "Log",
+    ]);
+
+    for job in jobs {
+        let pid = job.pid.map(|pid| pid.to_string()).unwrap_or_default();
+        let log = job
+            .log
+            .as_ref()
+            .map(|log| log.display().to_string())
+            .unwrap_or_default();
+
+        table.add_row(row![
+            FY -> job.id,
+            FY -> &job.alias,
+            Fb -> job.args.join(" "),
+            Fw -> pid,
+            Fg -> job.status(),
+            Fw -> job.started.format("%Y-%m-%d %H:%M:%S"),
+            Fw -> log,
+        ]);
+    }
+
+    // forced color explicitly. works in pipes
+    print_table(&table)?;
+
+    Ok(())
+}
+
+/// Prints a terminal table of the scheduled scripts with their next run.
+pub fn list_schedules(
+    scripts: &[ScheduledScript],
+    last_fire: &BTreeMap<String, DateTime<Local>>,
+) -> PierResult<()> {
+    let mut table = Table::new();
+
+    table.set_format(*COOL_FORMAT);
+    // cyan titles
+    table.set_titles(row![
+        Fc -> "Alias",
+        Fc -> "Schedule",
+        Fc -> "Catch up",
+        Fc -> "Last due",
+        Fc -> "Next run",
+    ]);
+
+    for script in scripts {
+        let last = last_fire
+            .get(&script.alias)
+            .map(|last| last.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let next = script
+            .schedule
+            .upcoming(Local)
+            .next()
+            .map(|next| next.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+
+        table.add_row(row![
+            FY -> &script.alias,
+            Fb -> &script.expression,
+            Fw -> script.catch_up.name(),
+            Fw -> last,
+            Fg -> next,
+        ]);
+    }
+
+    // forced color explicitly. works in pipes
+    print_table(&table)?;
+
+    Ok(())
+}
+
+fn print_table(table: &Table) -> PierResult<()> {
+    table
+        .print_tty(true)
+        .map_err(|source| PierError::CommandExec { source })?;
+
+    Ok(())
+}
+
+/// Prints an event of pier, the progress of runs only when verbose.
+pub fn event(event: &Event, verbose: bool) {
+    match event {
+        Event::ScriptStarted { alias } if verbose => {
+            println!("Starting script \"{}\"", alias);
+            println!("-------------------------");
+        }
+        Event::StepStarted {
+            step,
+            steps,
+            command,
+            ..
+        } if verbose => println!("Step {}/{}: {}", step, steps, command),
+        Event::ScriptFinished { .. } if verbose => {
+            println!("-------------------------");
+            println!("Script complete");
+        }
+        Event::ScriptStarted { .. } | Event::StepStarted { .. } | Event::ScriptFinished { .. } => (),
+        Event::HistoryFailed { error } => eprintln!("{}", error),
+        Event::Listening { address } => println!("Listening on {}", address),
+        Event::Request {
+            method,
+            url,
+            status: Ok(status),
+        } => println!("{} {} {}", method, url, status),
+        Event::Request {
+            method,
+            url,
+            status: Err(err),
+        } => eprintln!("{} {} {}", method, url, err),
+        Event::ConfigReloaded { path } => println!("Reloaded {}", path.display()),
+        Event::ConfigReloadFailed { error } => eprintln!("{}", error),
+        Event::MissedRunsSkipped { alias } => println!("Skipped missed runs of {}", alias),
+        Event::ScheduledRunStarted { alias } => println!("Running {}", alias),
+        Event::ScheduledRunFinished {
+            alias,
+            exit_code: Some(code),
+        } => println!("Finished {} with {}", alias, code),
+        Event::ScheduledRunFinished {
+            alias,
+            exit_code: None,
+        } => println!("Finished {} by signal", alias),
+        Event::ScheduledRunFailed { alias, error } => {
+            eprintln!("error: Unable to check run of {}: {}", alias, error)
+        }
+        Event::SourceSkipped { git, error } => {
+            eprintln!("warning: Skipped the scripts of {}: {}", git, error)
+        }
+    }
+}
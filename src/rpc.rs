@@ -0,0 +1,342 @@
+use std::io::{self, prelude::*, BufReader};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use snafu::ResultExt;
+
+use super::error::*;
+use super::script::Script;
+use super::{Pier, PierResult};
+
+// Error codes defined by JSON-RPC 2.0, errors of pier itself use SERVER_ERROR.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Deserialize, Debug)]
+struct RpcRequest {
+    // Missing for notifications, which don't get a response.
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ListParams {
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AliasParams {
+    alias: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct AddParams {
+    alias: String,
+    command: String,
+    description: Option<String>,
+    tags: Option<Vec<String>>,
+    depends_on: Option<Vec<String>>,
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct EditParams {
+    alias: String,
+    command: Option<String>,
+    description: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RunParams {
+    alias: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchParams {
+    query: String,
+}
+
+type Writer = Arc<Mutex<io::Stdout>>;
+
+/// Answers line delimited JSON-RPC 2.0 requests from stdin on stdout until stdin is closed.
+///
+/// The methods are `config`, `list`, `show`, `search`, `add`, `edit` and `run`. While a script
+/// runs, every line of its output is sent as a `run/output` notification, and the response with
+/// the exit code follows once it has finished.
+pub fn rpc(mut pier: Pier) -> PierResult<()> {
+    let writer: Writer = Arc::new(Mutex::new(io::stdout()));
+    let mut runs: Vec<JoinHandle<()>> = vec![];
+
+    for line in io::stdin().lock().lines() {
+        let line = line.context(CommandExec)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request: RpcRequest = match serde_json::from_str::<Value>(&line) {
+            Ok(value) => {
+                match serde_json::from_value(value) {
+                    Ok(request) => request,
+                    Err(err) => {
+                        let error = (INVALID_REQUEST, err.to_string(), None);
+                        send(&writer, &error_response(Value::Null, error));
+                        continue;
+                    }
+                }
+            }
+            Err(err) => {
+                let error = (PARSE_ERROR, err.to_string(), None);
+                send(&writer, &error_response(Value::Null, error));
+                continue;
+            }
+        };
+        let id = request.id.clone().unwrap_or(Value::Null);
+
+        // Runs answer from their own thread once the script has finished.
+        if request.method == "run" {
+            match start_run(&pier, request.params, id.clone(), writer.clone()) {
+                Ok(run) => runs.push(run),
+                Err(error) => {
+                    if request.id.is_some() {
+                        send(&writer, &error_response(id, error))
+                    }
+                }
+            }
+            continue;
+        }
+
+        let response = match handle(&mut pier, &request.method, request.params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => error_response(id, error),
+        };
+        if request.id.is_some() {
+            send(&writer, &response);
+        }
+    }
+
+    for run in runs {
+        let _ = run.join();
+    }
+
+    Ok(())
+}
+
+type RpcError = (i64, String, Option<Value>);
+
+fn handle(pier: &mut Pier, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "config" => Ok(json!({ "path": pier.path })),
+        "list" => {
+            let params: ListParams = parse_params(params)?;
+            let scripts = pier
+                .config
+                .scripts
+                .iter()
+                .filter(|(_, script)| {
+                    match (&params.tags, &script.tags) {
+                        (Some(tags), Some(script_tags)) => {
+                            tags.iter().any(|tag| script_tags.contains(tag))
+                        }
+                        (Some(_), None) => false,
+                        (None, _) => true,
+                    }
+                })
+                .map(|(alias, script)| script_json(alias, script))
+                .collect::<PierResult<Vec<Value>>>()
+                .map_err(pier_error)?;
+            Ok(Value::Array(scripts))
+        }
+        "show" => {
+            let params: AliasParams = parse_params(params)?;
+            let script = pier.fetch_script(&params.alias).map_err(pier_error)?;
+            script_json(&script.alias, script).map_err(pier_error)
+        }
+        "search" => {
+            let params: SearchParams = parse_params(params)?;
+            let query = params.query.to_lowercase();
+            let scripts = pier
+                .config
+                .scripts
+                .iter()
+                .filter(|(alias, script)| {
+                    std::iter::once(alias.as_str())
+                        .chain(std::iter::once(script.command.as_str()))
+                        .chain(script.description.as_deref())
+                        .chain(script.tags.iter().flatten().map(String::as_str))
+                        .any(|text| text.to_lowercase().contains(&query))
+                })
+                .map(|(alias, script)| script_json(alias, script))
+                .collect::<PierResult<Vec<Value>>>()
+                .map_err(pier_error)?;
+            Ok(Value::Array(scripts))
+        }
+        "add" => {
+            let params: AddParams = parse_params(params)?;
+            add(pier, params).map_err(pier_error)
+        }
+        "edit" => {
+            let params: EditParams = parse_params(params)?;
+            edit(pier, params).map_err(pier_error)
+        }
+        _ => {
+            Err((
+                METHOD_NOT_FOUND,
+                format!("Method not found: {}", method),
+                None,
+            ))
+        }
+    }
+}
+
+fn add(pier: &mut Pier, params: AddParams) -> PierResult<Value> {
+    let script = Script {
+        alias: params.alias.clone(),
+        command: params.command,
+        description: params.description,
+        tags: params.tags,
+        depends_on: params.depends_on,
+        ..Script::default()
+    };
+    let result = script_json(&params.alias, &script)?;
+    pier.add_script(script, params.force)?;
+    pier.write()?;
+
+    Ok(result)
+}
+
+fn edit(pier: &mut Pier, params: EditParams) -> PierResult<Value> {
+    let EditParams {
+        alias,
+        command,
+        description,
+        tags,
+    } = params;
+    let script = pier
+        .update_script(&alias, |script| {
+            if let Some(command) = command {
+                script.command = command;
+            }
+            if let Some(description) = description {
+                script.description = Some(description);
+            }
+            if let Some(tags) = tags {
+                script.tags = Some(tags);
+            }
+        })?
+        .clone();
+    let result = script_json(&script.alias, &script)?;
+    pier.write()?;
+
+    Ok(result)
+}
+
+/// Starts a script in a child pier process and streams its output from a thread.
+fn start_run(
+    pier: &Pier,
+    params: Value,
+    id: Value,
+    writer: Writer,
+) -> Result<JoinHandle<()>, RpcError> {
+    let params: RunParams = parse_params(params)?;
+    pier.fetch_script(&params.alias).map_err(pier_error)?;
+
+    let mut child = pier
+        .pier_command()
+        .map_err(pier_error)?
+        .arg("run")
+        .arg(&params.alias)
+        .arg("--")
+        .args(&params.args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(CommandExec)
+        .map_err(pier_error)?;
+
+    let stdout = child.stdout.take().expect("Child stdout is piped");
+    let stderr = child.stderr.take().expect("Child stderr is piped");
+
+    Ok(thread::spawn(move || {
+        thread::scope(|scope| {
+            for (stream, reader) in [
+                ("stdout", Box::new(stdout) as Box<dyn Read + Send>),
+                ("stderr", Box::new(stderr)),
+            ] {
+                let writer = &writer;
+                let id = &id;
+                scope.spawn(move || {
+                    for line in BufReader::new(reader).lines().map_while(Result::ok) {
+                        send(
+                            writer,
+                            &json!({
+                                "jsonrpc": "2.0",
+                                "method": "run/output",
+                                "params": { "id": id, "stream": stream, "line": line },
+                            }),
+                        );
+                    }
+                });
+            }
+        });
+
+        let response = match child.wait() {
+            Ok(status) => {
+                json!({ "jsonrpc": "2.0", "id": id, "result": { "exit_code": status.code() } })
+            }
+            Err(err) => error_response(id, (SERVER_ERROR, err.to_string(), None)),
+        };
+        send(&writer, &response);
+    }))
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    // Methods without required parameters can be called without params.
+    let params = match params {
+        Value::Null => json!({}),
+        params => params,
+    };
+
+    serde_json::from_value(params).map_err(|err| (INVALID_PARAMS, err.to_string(), None))
+}
+
+fn pier_error(err: PierError) -> RpcError {
+    let kind = format!("{:?}", PierErrorKind::from(&err));
+    (SERVER_ERROR, err.to_string(), Some(json!({ "kind": kind })))
+}
+
+fn script_json(alias: &str, script: &Script) -> PierResult<Value> {
+    let mut value = serde_json::to_value(script).context(JsonSerialize)?;
+    value["alias"] = json!(alias);
+
+    Ok(value)
+}
+
+fn error_response(id: Value, (code, message, data): RpcError) -> Value {
+    let mut error = json!({ "code": code, "message": message });
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+/// Writes a message as a single line. Errors are ignored since the client has gone away.
+fn send(writer: &Writer, message: &Value) {
+    let mut stdout = writer.lock().expect("Stdout lock poisoned");
+    let _ = writeln!(stdout, "{}", message);
+    let _ = stdout.flush();
+}
@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Stdio};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local};
+use cron::Schedule;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+
+use super::defaults::*;
+use super::error::*;
+use super::events::Event;
+use super::{Pier, PierResult};
+
+/// What the daemon does with the runs of a script that were missed while it wasn't running.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CatchUp {
+    // Drop the missed runs, like cron does.
+    #[default]
+    Skip,
+    // Run the script once for all of the missed runs.
+    Once,
+    // Run the script for every missed run, one after the other.
+    All,
+}
+
+impl CatchUp {
+    pub fn name(self) -> &'static str {
+        match self {
+            CatchUp::Skip => "skip",
+            CatchUp::Once => "once",
+            CatchUp::All => "all",
+        }
+    }
+}
+
+/// A script with a schedule.
+#[derive(Debug, Clone)]
+pub struct ScheduledScript {
+    pub alias: String,
+    pub expression: String,
+    pub schedule: Schedule,
+    pub catch_up: CatchUp,
+}
+
+impl ScheduledScript {
+    /// The times the script was due to run after its last run, from oldest to newest, with the
+    /// missed runs already handled by the catch up policy.
+    pub fn due_runs(
+        &self,
+        last_fire: DateTime<Local>,
+        now: DateTime<Local>,
+    ) -> Vec<DateTime<Local>> {
+        let mut due: Vec<DateTime<Local>> = self
+            .schedule
+            .after(&now)
+            .rev()
+            .take_while(|fire| *fire > last_fire)
+            .take(SCHEDULE_CATCH_UP_LIMIT)
+            .collect();
+        due.reverse();
+
+        match self.catch_up {
+            CatchUp::Skip => {
+                due.retain(|fire| (now - *fire).num_seconds() <= SCHEDULE_GRACE_SECONDS);
+                due
+            }
+            CatchUp::Once => due.pop().into_iter().collect(),
+            CatchUp::All => due,
+        }
+    }
+}
+
+/// Parses a crontab expression with five fields, or six fields when the first one holds the
+/// seconds. Names like @daily are accepted as well.
+pub fn parse_schedule(expression: &str) -> PierResult<Schedule> {
+    let fields: Vec<&str> = expression.split_whitespace().collect();
+    let cron_expression = match fields.len() {
+        5 => {
+            format!(
+                "0 {} {}",
+                fields[..4].join(" "),
+                crontab_weekdays(fields[4])
+            )
+        }
+        6 => format!("{} {}", fields[..5].join(" "), crontab_weekdays(fields[5])),
+        _ => expression.to_string(),
+    };
+
+    Schedule::from_str(&cron_expression).context(InvalidSchedule { expression })
+}
+
+const WEEKDAYS: [&str; 8] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT", "SUN"];
+
+/// Crontab numbers the weekdays from 0 for sunday, while the cron parser starts at 1. Names mean
+/// the same to both, so the numbers are replaced by names.
+fn crontab_weekdays(field: &str) -> String {
+    field
+        .split(',')
+        .map(|part| {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, Some(step)),
+                None => (part, None),
+            };
+            let range = range
+                .split('-')
+                .map(|day| {
+                    match day.parse::<usize>() {
+                        Ok(number) if number < WEEKDAYS.len() => WEEKDAYS[number].to_string(),
+                        _ => day.to_string(),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("-");
+
+            match step {
+                Some(step) => format!("{}/{}", range, step),
+                None => range,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The scripts of the config which have a schedule, ordered by alias.
+pub fn scheduled_scripts(pier: &Pier) -> PierResult<Vec<ScheduledScript>> {
+    pier.config
+        .scripts
+        .iter()
+        .filter_map(|(alias, script)| {
+            script.schedule.as_ref().map(|expression| {
+                Ok(ScheduledScript {
+                    alias: alias.clone(),
+                    expression: expression.clone(),
+                    schedule: parse_schedule(expression)?,
+                    catch_up: script.catch_up.unwrap_or_default(),
+                })
+            })
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct ScheduleFile {
+    #[serde(default)]
+    last_fire: BTreeMap<String, DateTime<Local>>,
+}
+
+/// Store of the last time every scheduled script was due, so the daemon knows which runs it
+/// missed while it wasn't running.
+#[derive(Debug)]
+pub struct ScheduleState {
+    path: PathBuf,
+}
+
+impl ScheduleState {
+    pub fn new(path: PathBuf) -> Self {
+        ScheduleState { path }
+    }
+
+    /// Opens the schedule state in the pier state directory.
+    pub fn open() -> PierResult<Self> {
+        Ok(ScheduleState::new(state_dir()?.join(SCHEDULE_FILE)))
+    }
+
+    /// The last time every scheduled script was due. A missing state file has no entries.
+    pub fn last_fires(&self) -> PierResult<BTreeMap<String, DateTime<Local>>> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new());
+        }
+
+        let content = fs::read_to_string(&self.path).context(ScheduleRead { path: &self.path })?;
+        let state: ScheduleFile =
+            toml::from_str(&content).context(ScheduleParse { path: &self.path })?;
+
+        Ok(state.last_fire)
+    }
+
+    /// Replaces the state file in one go so readers never see a partially written state.
+    pub fn save(&self, last_fire: &BTreeMap<String, DateTime<Local>>) -> PierResult<()> {
+        if let Some(parent_dir) = self.path.parent() {
+            fs::create_dir_all(parent_dir).context(CreateDirectory)?;
+        }
+
+        let tmp_path = self.path.with_extension("toml.tmp");
+        let content = toml::to_string(&ScheduleFile {
+            last_fire: last_fire.clone(),
+        })
+        .context(TomlSerialize)?;
+
+        fs::write(&tmp_path, content).context(ScheduleWrite { path: &tmp_path })?;
+        fs::rename(&tmp_path, &self.path).context(ScheduleWrite { path: &self.path })?;
+
+        Ok(())
+    }
+}
+
+/// Runs the scheduled scripts of the config file whenever they are due. Runs of the same script
+/// never overlap, a run that is due while the previous one is still going waits for it. With
+/// once set, it only runs the scripts that are due right now and returns when they finished.
+pub fn daemon(mut pier: Pier, once: bool) -> PierResult<()> {
+    let config = pier.path.clone();
+    let verbose = pier.verbose;
+    let mut scripts = scheduled_scripts(&pier)?;
+    let mut config_modified = modified(&config);
+
+    let state = ScheduleState::open()?;
+    let mut last_fire = state.last_fires()?;
+    let mut pending: BTreeMap<String, usize> = BTreeMap::new();
+    let mut running: BTreeMap<String, Child> = BTreeMap::new();
+    let mut first_tick = true;
+
+    loop {
+        // Pick up changes to the config file without restarting the daemon.
+        if !once && modified(&config) != config_modified {
+            config_modified = modified(&config);
+            match Pier::from_file(config.clone(), verbose)
+                .and_then(|new_pier| Ok((scheduled_scripts(&new_pier)?, new_pier)))
+            {
+                Ok((new_scripts, mut new_pier)) => {
+                    pier.emit(Event::ConfigReloaded {
+                        path: config.clone(),
+                    });
+                    if let Some(handler) = pier.events.take() {
+                        new_pier.set_event_handler(handler);
+                    }
+                    scripts = new_scripts;
+                    pier = new_pier;
+                }
+                Err(err) => pier.emit(Event::ConfigReloadFailed {
+                    error: err.to_string(),
+                }),
+            }
+        }
+
+        if first_tick || !once {
+            let now = Local::now();
+            let mut changed = false;
+
+            for script in &scripts {
+                let last = match last_fire.get(&script.alias) {
+                    Some(last) => *last,
+                    None => {
+                        // Newly scheduled scripts have no missed runs.
+                        last_fire.insert(script.alias.clone(), now);
+                        changed = true;
+                        continue;
+                    }
+                };
+
+                let latest = match script.schedule.after(&now).next_back() {
+                    Some(latest) if latest > last => latest,
+                    _ => continue,
+                };
+                last_fire.insert(script.alias.clone(), latest);
+                changed = true;
+
+                let due = script.due_runs(last, now);
+                if due.is_empty() {
+                    pier.emit(Event::MissedRunsSkipped {
+                        alias: script.alias.clone(),
+                    });
+                }
+                *pending.entry(script.alias.clone()).or_default() += due.len();
+            }
+
+            if changed {
+                state.save(&last_fire)?;
+            }
+            first_tick = false;
+        }
+
+        running.retain(|alias, child| {
+            match child.try_wait() {
+                Ok(Some(status)) => {
+                    pier.emit(Event::ScheduledRunFinished {
+                        alias: alias.clone(),
+                        exit_code: status.code(),
+                    });
+                    false
+                }
+                Ok(None) => true,
+                Err(err) => {
+                    pier.emit(Event::ScheduledRunFailed {
+                        alias: alias.clone(),
+                        error: err.to_string(),
+                    });
+                    false
+                }
+            }
+        });
+
+        for (alias, count) in pending.iter_mut() {
+            if *count == 0 || running.contains_key(alias) {
+                continue;
+            }
+            *count -= 1;
+
+            pier.emit(Event::ScheduledRunStarted {
+                alias: alias.clone(),
+            });
+            let child = pier
+                .pier_command()?
+                .args(["run", "--log"])
+                .arg(alias)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .context(CommandExec)?;
+            running.insert(alias.clone(), child);
+        }
+        pending.retain(|_, count| *count > 0);
+
+        if once && pending.is_empty() && running.is_empty() {
+            return Ok(());
+        }
+
+        thread::sleep(Duration::from_millis(SCHEDULE_POLL_INTERVAL_MS));
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
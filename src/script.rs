@@ -1,21 +1,79 @@
 use super::error::*;
+use super::logs::RunLog;
+use super::schedule::CatchUp;
+use super::watch::WatchOpts;
 use super::PierResult;
 use serde::{Deserialize, Serialize};
-use snafu::ResultExt;
-use std::fs::File;
+use snafu::{OptionExt, ResultExt};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
 use std::io::prelude::*;
 use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Output, Stdio};
-use tempfile;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Script {
     #[serde(skip)]
     pub alias: String,
+    // The git repository of the source the script comes from, see Source.
+    #[serde(skip)]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub command: String,
     pub description: Option<String>,
     pub reference: Option<String>,
+    // The directory the script runs in, the current directory when not set.
+    pub cwd: Option<PathBuf>,
     pub tags: Option<Vec<String>>,
+    // Aliases of the scripts that have to run successfully before this one.
+    pub depends_on: Option<Vec<String>>,
+    // Capture the output of every run in a log file.
+    pub log: Option<bool>,
+    // A cron expression for when pier daemon runs this script.
+    pub schedule: Option<String>,
+    // What pier daemon does with the runs that were missed while it wasn't running.
+    pub catch_up: Option<CatchUp>,
+    // Hooks which run around this script in addition to the default hooks.
+    pub hooks: Option<Hooks>,
+    // What pier watch looks at to re-run this script.
+    pub watch: Option<WatchOpts>,
+    // Runs these steps in order instead of the command when set.
+    pub steps: Option<Vec<Step>>,
+}
+
+/// A single step of a multi-step script.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Step {
+    pub command: String,
+    // Overrides the default interpreter for this step.
+    pub interpreter: Option<Vec<String>>,
+    pub cwd: Option<PathBuf>,
+    // Keep running the following steps even if this one fails.
+    #[serde(default)]
+    pub continue_on_error: bool,
+    pub env: Option<BTreeMap<String, String>>,
+}
+
+/// Commands which run around a script. They receive PIER_ALIAS and PIER_ARGS, and the hooks that
+/// run after the script also receive PIER_EXIT_CODE and PIER_DURATION_MS.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Hooks {
+    // Runs before the script, a failing before hook stops the script from running.
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub on_success: Option<String>,
+    pub on_failure: Option<String>,
+}
+
+/// Where the output of a command goes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OutputTarget<'a> {
+    // Copy the output to this log as well.
+    pub log: Option<&'a RunLog>,
+    // Pass the output through to the terminal instead of capturing it.
+    pub passthrough: bool,
 }
 
 impl Script {
@@ -42,68 +100,261 @@ impl Script {
             }
         }
     }
+    /// Whether the script runs the file it references, which it does when it has no command.
+    pub fn runs_reference(&self) -> bool {
+        self.reference.is_some() && self.command.is_empty() && self.steps.is_none()
+    }
+
+    /// The file the script references, none if the file doesn't exist.
+    pub fn reference_path(&self) -> Option<PathBuf> {
+        self.reference.as_deref().and_then(resolve_reference)
+    }
+
+    /// Reads the content of the referenced file.
+    pub fn read_reference(&self) -> PierResult<String> {
+        let path = self.reference_path().context(ReferenceNotFound {
+            alias: &self.alias,
+            reference: self.reference.clone().unwrap_or_default(),
+        })?;
+
+        fs::read_to_string(&path).context(ReferenceRead { path })
+    }
+
+    /// Executes the referenced file with the arguments.
+    pub fn run_reference(&self, args: Vec<String>, output: OutputTarget) -> PierResult<Output> {
+        let path = self.reference_path().context(ReferenceNotFound {
+            alias: &self.alias,
+            reference: self.reference.clone().unwrap_or_default(),
+        })?;
+
+        let mut command = Command::new(path);
+        command.args(args);
+        configure(&mut command, self.cwd.as_deref(), None);
+
+        execute(&mut command, output)
+    }
+
     /// Runs the script inline using something like sh -c "<script>" or python -c "<script."...
     pub fn run_with_cli_interpreter(
         &self,
-        interpreter: &Vec<String>,
+        interpreter: &[String],
         args: Vec<String>,
+        output: OutputTarget,
     ) -> PierResult<Output> {
-        // First item in interpreter is the binary
-        let cmd = Command::new(&interpreter[0])
-            // The following items after the binary is any commandline args that are necessary.
-            .args(&interpreter[1..])
-            .arg(&self.command)
-            .arg(&self.alias)
-            .args(&args)
-            .stderr(Stdio::piped())
-            .spawn()
-            .context(CommandExec)?
-            .wait_with_output()
-            .context(CommandExec)?;
-
-        Ok(cmd)
+        run_inline(
+            &self.command,
+            &self.alias,
+            interpreter,
+            &args,
+            self.cwd.as_deref(),
+            None,
+            output,
+        )
     }
 
     /// First creates a temporary file and then executes the file before removing it.
-    pub fn run_with_shebang(&self, args: Vec<String>) -> PierResult<Output> {
-        // Creates a temp directory to place our tempfile inside.
-        let tmpdir = tempfile::Builder::new()
-            .prefix("pier")
-            .tempdir()
-            .context(ExecutableTempFileCreate)?;
+    pub fn run_with_shebang(&self, args: Vec<String>, output: OutputTarget) -> PierResult<Output> {
+        run_executable(
+            &self.command,
+            &self.alias,
+            &args,
+            self.cwd.as_deref(),
+            None,
+            output,
+        )
+    }
+}
 
-        let exec_file_path = tmpdir.path().join(&self.alias);
+impl Step {
+    pub fn has_shebang(&self) -> bool {
+        match self.command.lines().next() {
+            Some(line) => line.starts_with("#!"),
+            None => false,
+        }
+    }
 
-        // Creating the file inside a closure is convenient because rust will automatically handle
-        // closing the file for us so we can go ahead and execute it after writing to it and setting the file permissions.
-        {
-            let mut exec_file = File::create(&exec_file_path).context(ExecutableTempFileCreate)?;
+    /// Runs the step with its own interpreter, working directory and environment. Without a
+    /// working directory of its own, it runs in the one of the script.
+    pub fn run(
+        &self,
+        alias: &str,
+        default_interpreter: &[String],
+        default_cwd: Option<&Path>,
+        args: &[String],
+        output: OutputTarget,
+    ) -> PierResult<Output> {
+        let cwd = self.cwd.as_deref().or(default_cwd);
+        let env = self.env.as_ref();
+        let interpreter = match &self.interpreter {
+            Some(interpreter) => interpreter,
+            None => default_interpreter,
+        };
 
-            exec_file
-                .write(self.command.as_bytes())
-                .context(ExecutableTempFileCreate)?;
+        match self.has_shebang() {
+            true => run_executable(&self.command, alias, args, cwd, env, output),
+            false => run_inline(&self.command, alias, interpreter, args, cwd, env, output),
+        }
+    }
+}
 
-            let mut permissions = exec_file
-                .metadata()
-                .context(ExecutableTempFileCreate)?
-                .permissions();
+impl Hooks {
+    /// Runs a hook command inline with the interpreter and extra environment variables.
+    pub fn run(
+        command: &str,
+        interpreter: &[String],
+        env: &BTreeMap<String, String>,
+        output: OutputTarget,
+    ) -> PierResult<Output> {
+        // Hooks are not part of the run log.
+        let output = OutputTarget {
+            log: None,
+            ..output
+        };
+        run_inline(command, "hook", interpreter, &[], None, Some(env), output)
+    }
+}
+
+/// The name of the program in the shebang of the content, like python3 for
+/// `#!/usr/bin/env python3`.
+pub fn shebang_interpreter(content: &str) -> Option<&str> {
+    let shebang = content.lines().next()?.strip_prefix("#!")?;
+
+    let mut words = shebang.split_whitespace();
+    let interpreter = words.next()?.rsplit('/').next()?;
+    match interpreter {
+        "env" => words.find(|word| !word.starts_with('-')),
+        _ => Some(interpreter),
+    }
+}
+
+/// Finds the file of a reference. References with a slash are paths, where ~/ stands for the
+/// home directory, and other references are looked up on $PATH.
+pub fn resolve_reference(reference: &str) -> Option<PathBuf> {
+    let path = match reference.strip_prefix("~/") {
+        Some(relative) => dirs::home_dir()?.join(relative),
+        None => PathBuf::from(reference),
+    };
+    if reference.contains('/') {
+        return Some(path).filter(|path| path.is_file());
+    }
 
-            // Set the file permissions to allow read and execute for the current user.
-            permissions.set_mode(0o500);
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(reference))
+        .find(|path| {
+            path.metadata()
+                .map_or(false, |meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        })
+}
+
+/// Sets the working directory and environment variables of a command if they are given.
+fn configure(command: &mut Command, cwd: Option<&Path>, env: Option<&BTreeMap<String, String>>) {
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+    if let Some(env) = env {
+        command.envs(env);
+    }
+}
+
+/// Waits for the command to finish. The output is passed through or captured as asked, and
+/// copied to the log when one is given.
+fn execute(command: &mut Command, output: OutputTarget) -> PierResult<Output> {
+    match (output.log, output.passthrough) {
+        (Some(log), passthrough) => log.tee(command, passthrough),
+        (None, true) => {
+            let status = command.status().context(CommandExec)?;
+
+            Ok(Output {
+                status,
+                stdout: vec![],
+                stderr: vec![],
+            })
+        }
+        (None, false) => {
+            let cmd = command
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .context(CommandExec)?
+                .wait_with_output()
+                .context(CommandExec)?;
 
-            exec_file
-                .set_permissions(permissions)
-                .context(ExecutableTempFileCreate)?;
+            Ok(cmd)
         }
+    }
+}
+
+fn run_inline(
+    content: &str,
+    alias: &str,
+    interpreter: &[String],
+    args: &[String],
+    cwd: Option<&Path>,
+    env: Option<&BTreeMap<String, String>>,
+    output: OutputTarget,
+) -> PierResult<Output> {
+    // First item in interpreter is the binary
+    let (binary, interpreter_args) = interpreter
+        .split_first()
+        .context(EmptyInterpreter { alias })?;
+    let mut command = Command::new(binary);
+    // The following items after the binary is any commandline args that are necessary.
+    command
+        .args(interpreter_args)
+        .arg(content)
+        .arg(alias)
+        .args(args);
+    configure(&mut command, cwd, env);
+
+    execute(&mut command, output)
+}
+
+/// The name of the file a script is written to, the last part of an alias in a group.
+pub(crate) fn file_name(alias: &str) -> &str {
+    alias.rsplit('/').find(|part| !part.is_empty()).unwrap_or("script")
+}
+
+fn run_executable(
+    content: &str,
+    alias: &str,
+    args: &[String],
+    cwd: Option<&Path>,
+    env: Option<&BTreeMap<String, String>>,
+    output: OutputTarget,
+) -> PierResult<Output> {
+    // Creates a temp directory to place our tempfile inside.
+    let tmpdir = tempfile::Builder::new()
+        .prefix("pier")
+        .tempdir()
+        .context(ExecutableTempFileCreate)?;
 
-        let cmd = Command::new(exec_file_path)
-            .stderr(Stdio::piped())
-            .args(&args)
-            .spawn()
-            .context(CommandExec)?
-            .wait_with_output()
-            .context(CommandExec)?;
+    let exec_file_path = tmpdir.path().join(file_name(alias));
 
-        Ok(cmd)
+    // Creating the file inside a closure is convenient because rust will automatically handle
+    // closing the file for us so we can go ahead and execute it after writing to it and setting the file permissions.
+    {
+        let mut exec_file = File::create(&exec_file_path).context(ExecutableTempFileCreate)?;
+
+        exec_file
+            .write_all(content.as_bytes())
+            .context(ExecutableTempFileCreate)?;
+
+        let mut permissions = exec_file
+            .metadata()
+            .context(ExecutableTempFileCreate)?
+            .permissions();
+
+        // Set the file permissions to allow read and execute for the current user.
+        permissions.set_mode(0o500);
+
+        exec_file
+            .set_permissions(permissions)
+            .context(ExecutableTempFileCreate)?;
     }
+
+    let mut command = Command::new(exec_file_path);
+    command.args(args);
+    configure(&mut command, cwd, env);
+
+    execute(&mut command, output)
 }
@@ -0,0 +1,361 @@
+use std::fs;
+use std::io::{self, prelude::*, BufReader, Cursor};
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use std::process::{Child, Stdio};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use snafu::{ensure, ResultExt};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use super::defaults::*;
+use super::error::*;
+use super::events::Event;
+use super::{Pier, PierResult};
+
+/// Settings of the HTTP API server.
+#[derive(Debug, Clone, Default)]
+pub struct ServeOpts {
+    // The address to listen on, used when no socket is given.
+    pub listen: Option<String>,
+    // The path of a unix socket to listen on.
+    pub socket: Option<PathBuf>,
+    // Clients have to send this token as a bearer token, required when listening on an address.
+    pub token: Option<String>,
+    // The aliases of the scripts that clients may run.
+    pub allow: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct RunRequest {
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+/// Serves the scripts of the config over HTTP until the process is stopped.
+///
+/// * `GET /scripts` lists the scripts.
+/// * `GET /scripts/<alias>` shows a script.
+/// * `POST /scripts/<alias>/run` runs an allowed script and streams its output as JSON lines.
+pub fn serve(pier: &Pier, opts: &ServeOpts) -> PierResult<()> {
+    let server = match &opts.socket {
+        Some(socket) => {
+            remove_stale_socket(socket)?;
+            let server = Server::http_unix(socket)
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+                .context(ServeListen {
+                    address: socket.display().to_string(),
+                })?;
+            pier.emit(Event::Listening {
+                address: socket.display().to_string(),
+            });
+            server
+        }
+        None => {
+            ensure!(opts.token.is_some(), ServeTokenMissing);
+
+            let address = opts.listen.as_deref().unwrap_or(SERVE_DEFAULT_LISTEN);
+            let server = Server::http(address)
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+                .context(ServeListen { address })?;
+            pier.emit(Event::Listening {
+                address: format!("http://{}", server.server_addr()),
+            });
+            server
+        }
+    };
+
+    // Every request gets its own thread, so a long running script doesn't hold up the others.
+    thread::scope(|scope| {
+        for request in server.incoming_requests() {
+            scope.spawn(move || {
+                let method = request.method().to_string();
+                let url = request.url().to_string();
+                let status = handle(pier, opts, request).map_err(|err| err.to_string());
+                pier.emit(Event::Request {
+                    method,
+                    url,
+                    status,
+                });
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// A unix socket left behind by a previous server would make binding the socket fail.
+fn remove_stale_socket(socket: &PathBuf) -> PierResult<()> {
+    if let Ok(metadata) = fs::symlink_metadata(socket) {
+        if metadata.file_type().is_socket() {
+            fs::remove_file(socket).context(ServeListen {
+                address: socket.display().to_string(),
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Answers a single request and returns the status code of the response.
+fn handle(pier: &Pier, opts: &ServeOpts, mut request: Request) -> PierResult<u16> {
+    if let Some(token) = &opts.token {
+        let authorized = request.headers().iter().any(|header| {
+            header.field.equiv("Authorization")
+                && constant_time_eq(header.value.as_str(), &format!("Bearer {}", token))
+        });
+        if !authorized {
+            return respond_error(request, 401, "Missing or invalid token");
+        }
+    }
+
+    let path = request.url().split('?').next().unwrap_or_default();
+    let path = match percent_decode(path.trim_matches('/')) {
+        Some(path) => path,
+        None => return respond_error(request, 400, "Invalid percent-encoding in the path"),
+    };
+    // Aliases can have slashes in them, so the route is found from both ends of the path.
+    let route = match path.strip_prefix("scripts/") {
+        Some(alias) => match alias.strip_suffix("/run") {
+            Some(alias) if request.method() == &Method::Post => Route::Run(alias),
+            _ => Route::Script(alias),
+        },
+        None if path == "scripts" => Route::Scripts,
+        None => Route::NotFound,
+    };
+
+    let method = request.method().clone();
+    match (&method, route) {
+        (Method::Get, Route::Scripts) => {
+            let scripts: Vec<Value> = pier
+                .config
+                .scripts
+                .iter()
+                .map(|(alias, script)| {
+                    json!({
+                        "alias": alias,
+                        "description": script.description,
+                        "tags": script.tags,
+                        "runnable": is_allowed(pier, opts, alias),
+                    })
+                })
+                .collect();
+            respond_json(request, 200, &Value::Array(scripts))
+        }
+        (Method::Get, Route::Script(alias)) => {
+            let alias = match pier.resolve_alias(alias) {
+                Ok(alias) => alias,
+                Err(err) => return respond_error(request, 404, &err.to_string()),
+            };
+            let script = pier.fetch_script(&alias)?;
+            let mut value = serde_json::to_value(script).context(JsonSerialize)?;
+            value["alias"] = json!(alias);
+            value["runnable"] = json!(is_allowed(pier, opts, &alias));
+            respond_json(request, 200, &value)
+        }
+        (Method::Post, Route::Run(alias)) => {
+            let alias = match pier.resolve_alias(alias) {
+                Ok(alias) => alias,
+                Err(err) => return respond_error(request, 404, &err.to_string()),
+            };
+            if !is_allowed(pier, opts, &alias) {
+                return respond_error(request, 403, "The script is not allowed to run remotely");
+            }
+
+            let mut body = String::new();
+            request
+                .as_reader()
+                .read_to_string(&mut body)
+                .context(CommandExec)?;
+            let run: RunRequest = match body.trim() {
+                "" => RunRequest::default(),
+                body => {
+                    match serde_json::from_str(body) {
+                        Ok(run) => run,
+                        Err(err) => return respond_error(request, 400, &err.to_string()),
+                    }
+                }
+            };
+
+            let child = pier
+                .pier_command()?
+                .arg("run")
+                .arg(&alias)
+                .arg("--")
+                .args(&run.args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .context(CommandExec)?;
+
+            let response = Response::new(
+                200.into(),
+                vec![json_header("application/x-ndjson")],
+                RunStream::new(child),
+                None,
+                None,
+            );
+            request.respond(response).context(CommandExec)?;
+
+            Ok(200)
+        }
+        _ => respond_error(request, 404, "Not found"),
+    }
+}
+
+/// What a request asks for by its path.
+enum Route<'a> {
+    Scripts,
+    Script(&'a str),
+    Run(&'a str),
+    NotFound,
+}
+
+/// Whether the script with the full alias may run, the allowed aliases are resolved like any
+/// other alias.
+fn is_allowed(pier: &Pier, opts: &ServeOpts, alias: &str) -> bool {
+    opts.allow
+        .iter()
+        .any(|allowed| pier.resolve_alias(allowed).map_or(false, |allowed| allowed == alias))
+}
+
+/// Decodes the %XX escapes of a path, none when an escape is invalid or the result isn't UTF-8.
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' => {
+                let hex = bytes.get(index + 1..index + 3)?;
+                if !hex.iter().all(u8::is_ascii_hexdigit) {
+                    return None;
+                }
+                let hex = std::str::from_utf8(hex).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                index += 3;
+            }
+            byte => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+fn json_header(content_type: &str) -> Header {
+    Header::from_bytes("Content-Type", content_type).expect("Content type header is valid")
+}
+
+fn respond_json(request: Request, status: u16, value: &Value) -> PierResult<u16> {
+    let body = value.to_string().into_bytes();
+    let length = body.len();
+    let response = Response::new(
+        status.into(),
+        vec![json_header("application/json")],
+        Cursor::new(body),
+        Some(length),
+        None,
+    );
+    request.respond(response).context(CommandExec)?;
+
+    Ok(status)
+}
+
+fn respond_error(request: Request, status: u16, message: &str) -> PierResult<u16> {
+    respond_json(request, status, &json!({ "error": message }))
+}
+
+/// Compares the strings in a time which doesn't depend on where they differ, so the token can't
+/// be guessed from response times.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a
+            .bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// The output of a running script as JSON lines, which ends with a line holding the exit code.
+struct RunStream {
+    child: Child,
+    lines: Receiver<Value>,
+    buffer: Cursor<Vec<u8>>,
+    finished: bool,
+}
+
+impl RunStream {
+    fn new(mut child: Child) -> Self {
+        let (sender, lines) = channel();
+
+        let stdout = child.stdout.take().expect("Child stdout is piped");
+        let stderr = child.stderr.take().expect("Child stderr is piped");
+        for (stream, reader) in [
+            ("stdout", Box::new(stdout) as Box<dyn Read + Send>),
+            ("stderr", Box::new(stderr)),
+        ] {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(reader).lines().map_while(Result::ok) {
+                    // The client went away, the script keeps running until it finishes.
+                    let _ = sender.send(json!({ "stream": stream, "line": line }));
+                }
+            });
+        }
+
+        RunStream {
+            child,
+            lines,
+            buffer: Cursor::new(vec![]),
+            finished: false,
+        }
+    }
+
+    /// Fills the buffer with the next line, returns false once everything has been sent.
+    fn next_line(&mut self) -> io::Result<bool> {
+        let value = match self.lines.recv() {
+            Ok(value) => value,
+            Err(_) if self.finished => return Ok(false),
+            Err(_) => {
+                // Both streams are closed, so the script is done.
+                self.finished = true;
+                let status = self.child.wait()?;
+                json!({ "exit_code": status.code() })
+            }
+        };
+
+        let mut line = value.to_string().into_bytes();
+        line.push(b'\n');
+        self.buffer = Cursor::new(line);
+
+        Ok(true)
+    }
+}
+
+impl Read for RunStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.buffer.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            if !self.next_line()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+impl Drop for RunStream {
+    fn drop(&mut self) {
+        // Reaps the script when the client disconnected before it finished.
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,286 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, ResultExt};
+
+use super::defaults::*;
+use super::error::*;
+use super::export::sh_quote;
+use super::script::{file_name, resolve_reference};
+use super::{home, pier_err, Pier, PierResult};
+
+// Marks the shims written by pier, files without it are never removed.
+const SHIM_MARKER: &str = "# Installed by pier";
+
+/// A wrapper executable in a bin directory which runs a script of a config with pier.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Shim {
+    pub alias: String,
+    pub config: PathBuf,
+    pub path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct ShimsFile {
+    #[serde(default)]
+    shims: Vec<Shim>,
+}
+
+/// Store of the shims pier installed, so they can be renamed and removed with their scripts.
+pub struct Shims {
+    path: PathBuf,
+}
+
+impl Shims {
+    pub fn new(path: PathBuf) -> Self {
+        Shims { path }
+    }
+
+    /// Opens the shims in the pier state directory.
+    pub fn open() -> PierResult<Self> {
+        Ok(Shims::new(state_dir()?.join(SHIMS_FILE)))
+    }
+
+    /// Every installed shim, of all configs. A missing file has no shims.
+    pub fn load(&self) -> PierResult<Vec<Shim>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+
+        let content = fs::read_to_string(&self.path).context(ShimsRead { path: &self.path })?;
+        let file: ShimsFile = toml::from_str(&content).context(ShimsParse { path: &self.path })?;
+
+        Ok(file.shims)
+    }
+
+    /// Replaces the file in one go so readers never see a partially written list.
+    pub fn save(&self, shims: &[Shim]) -> PierResult<()> {
+        if let Some(parent_dir) = self.path.parent() {
+            fs::create_dir_all(parent_dir).context(CreateDirectory)?;
+        }
+
+        let tmp_path = self.path.with_extension("toml.tmp");
+        let content = toml::to_string(&ShimsFile {
+            shims: shims.to_vec(),
+        })
+        .context(TomlSerialize)?;
+
+        fs::write(&tmp_path, content).context(ShimsWrite { path: &tmp_path })?;
+        fs::rename(&tmp_path, &self.path).context(ShimsWrite { path: &self.path })?;
+
+        Ok(())
+    }
+}
+
+/// The bin directory shims go to when no other one is given.
+pub fn default_bin_dir() -> PierResult<PathBuf> {
+    match home!(".local/bin") {
+        Some(path) => Ok(path),
+        None => pier_err!(PierError::NoBinDir),
+    }
+}
+
+/// Writes a shim to the bin directory for every alias, which runs the script with this pier
+/// executable and config. Shims that are already installed are written again.
+///
+/// Refuses to replace files that pier didn't install and to shadow executables on $PATH.
+pub fn install(pier: &Pier, aliases: &[String], bin_dir: &Path) -> PierResult<Vec<Shim>> {
+    let config = config_path(pier.path())?;
+    let exe = env::current_exe().context(CommandExec)?;
+    let bin_dir = fs::canonicalize(bin_dir).unwrap_or_else(|_| bin_dir.to_path_buf());
+    let store = Shims::open()?;
+    let mut shims = store.load()?;
+
+    let mut installed = vec![];
+    for alias in aliases {
+        // Shims are recorded by the full alias, which is what removing and moving scripts use.
+        let alias = pier.resolve_alias(alias)?;
+        let shim = Shim {
+            path: bin_dir.join(file_name(&alias)),
+            alias,
+            config: config.clone(),
+        };
+        check_shadowing(&shim, &shims, &installed)?;
+        installed.push(shim);
+    }
+
+    fs::create_dir_all(&bin_dir).context(CreateDirectory)?;
+    for shim in &installed {
+        write_shim(shim, &exe)?;
+        shims.retain(|owned| owned.path != shim.path);
+        shims.push(shim.clone());
+    }
+    store.save(&shims)?;
+
+    Ok(installed)
+}
+
+/// Removes the shims of the config with one of the aliases, or all of them when no aliases are
+/// given, and returns the removed shims. Only looks in the bin directory when one is given.
+pub fn uninstall(
+    config: &Path,
+    aliases: Option<&[String]>,
+    bin_dir: Option<&Path>,
+) -> PierResult<Vec<Shim>> {
+    // Stores without a path, like the memory store, never have shims.
+    if config.as_os_str().is_empty() {
+        return Ok(vec![]);
+    }
+    let config = config_path(config)?;
+    let bin_dir = bin_dir.map(|dir| fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf()));
+    let store = Shims::open()?;
+
+    let (removed, kept): (Vec<Shim>, Vec<Shim>) =
+        store.load()?.into_iter().partition(|shim| {
+            shim.config == config
+                && aliases.map_or(true, |aliases| aliases.contains(&shim.alias))
+                && bin_dir.as_ref().map_or(true, |dir| shim.path.parent() == Some(dir))
+        });
+    if removed.is_empty() {
+        return Ok(removed);
+    }
+
+    for shim in &removed {
+        if is_shim(&shim.path) {
+            fs::remove_file(&shim.path).context(ShimsWrite { path: &shim.path })?;
+        }
+    }
+    store.save(&kept)?;
+
+    Ok(removed)
+}
+
+/// Moves the shims of a script to its new alias.
+pub fn rename(config: &Path, from_alias: &str, new_alias: &str) -> PierResult<()> {
+    if config.as_os_str().is_empty() {
+        return Ok(());
+    }
+    let config = config_path(config)?;
+    let store = Shims::open()?;
+    let mut shims = store.load()?;
+
+    // The shims of the script are checked as if they had the new alias already.
+    let owned: Vec<Shim> = shims
+        .iter()
+        .map(|shim| match shim.config == config && shim.alias == from_alias {
+            true => Shim {
+                alias: new_alias.to_string(),
+                ..shim.clone()
+            },
+            false => shim.clone(),
+        })
+        .collect();
+    let mut renamed: Vec<(Shim, Shim)> = vec![];
+    for shim in shims.iter().filter(|shim| shim.config == config && shim.alias == from_alias) {
+        let new_shim = Shim {
+            alias: new_alias.to_string(),
+            config: config.clone(),
+            path: shim.path.with_file_name(file_name(new_alias)),
+        };
+        let pending: Vec<Shim> = renamed.iter().map(|(_, new_shim)| new_shim.clone()).collect();
+        check_shadowing(&new_shim, &owned, &pending)?;
+        renamed.push((shim.clone(), new_shim));
+    }
+    if renamed.is_empty() {
+        return Ok(());
+    }
+
+    let exe = env::current_exe().context(CommandExec)?;
+    for (old_shim, new_shim) in renamed {
+        write_shim(&new_shim, &exe)?;
+        if is_shim(&old_shim.path) {
+            fs::remove_file(&old_shim.path).context(ShimsWrite { path: &old_shim.path })?;
+        }
+        shims.retain(|shim| shim.path != old_shim.path && shim.path != new_shim.path);
+        shims.push(new_shim);
+    }
+    store.save(&shims)?;
+
+    Ok(())
+}
+
+/// Config files are tracked by their absolute path, the shims run from anywhere.
+fn config_path(path: &Path) -> PierResult<PathBuf> {
+    ensure!(!path.as_os_str().is_empty(), NoConfigFile);
+
+    Ok(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+/// Fails when the shim would replace a file that isn't a shim of the same config, a shim of
+/// another script, like docker/clean and cargo/clean which are both named clean, or when another
+/// executable on $PATH has the name of the shim. Pending shims are about to be written as well.
+fn check_shadowing(shim: &Shim, owned: &[Shim], pending: &[Shim]) -> PierResult<()> {
+    let name = file_name(&shim.alias);
+    ensure!(
+        !name.is_empty() && !name.starts_with('.'),
+        InvalidScriptFileName { alias: &shim.alias }
+    );
+
+    let taken = owned
+        .iter()
+        .chain(pending)
+        .find(|other| other.path == shim.path && other.alias != shim.alias);
+    if let Some(other) = taken {
+        return ShimNameTaken {
+            alias: &shim.alias,
+            other: &other.alias,
+            path: &shim.path,
+        }
+        .fail();
+    }
+
+    let is_owned = |path: &Path| {
+        owned
+            .iter()
+            .any(|owned| owned.path == path && owned.config == shim.config)
+    };
+    ensure!(
+        !shim.path.exists() || is_owned(&shim.path),
+        ShimShadowsBinary {
+            alias: &shim.alias,
+            path: &shim.path
+        }
+    );
+    if let Some(binary) = resolve_reference(name) {
+        let binary = fs::canonicalize(&binary).unwrap_or(binary);
+        ensure!(
+            binary == shim.path || is_owned(&binary),
+            ShimShadowsBinary {
+                alias: &shim.alias,
+                path: binary
+            }
+        );
+    }
+
+    Ok(())
+}
+
+fn write_shim(shim: &Shim, exe: &Path) -> PierResult<()> {
+    let content = format!(
+        "#!/bin/sh\n{} for the script {}, remove it with pier uninstall {}.\n\
+         exec {} --config-file {} run {} -- \"$@\"\n",
+        SHIM_MARKER,
+        shim.alias,
+        shim.alias,
+        sh_quote(&exe.to_string_lossy()),
+        sh_quote(&shim.config.to_string_lossy()),
+        sh_quote(&shim.alias),
+    );
+
+    fs::write(&shim.path, content).context(ShimsWrite { path: &shim.path })?;
+    fs::set_permissions(&shim.path, fs::Permissions::from_mode(0o755))
+        .context(ShimsWrite { path: &shim.path })?;
+
+    Ok(())
+}
+
+/// Whether the file is still a shim written by pier, and not replaced by something else since.
+fn is_shim(path: &Path) -> bool {
+    match fs::read_to_string(path) {
+        Ok(content) => content.lines().nth(1).map_or(false, |line| line.starts_with(SHIM_MARKER)),
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,188 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use snafu::{ensure, ResultExt};
+
+use super::config::{Config, Source};
+use super::error::*;
+use super::events::Event;
+use super::store::{DirectoryStore, ScriptStore, TomlStore};
+use super::{pier_err, PierResult};
+
+// The config in the repository of a source when the source doesn't name one.
+const SOURCE_CONFIG_FILE: &str = "pier.toml";
+
+impl Source {
+    /// The directory in the cache with the clone of the repository.
+    pub fn cache_dir(&self) -> PierResult<PathBuf> {
+        let base = match dirs::cache_dir() {
+            Some(base) => base,
+            None => pier_err!(PierError::NoCacheDir),
+        };
+        // Every ref of a repository gets its own checkout.
+        let mut name = escape_file_name(&self.git);
+        if let Some(git_ref) = &self.git_ref {
+            name = format!("{}@{}", name, escape_file_name(git_ref));
+        }
+
+        Ok(base.join("pier").join("sources").join(name))
+    }
+
+    /// The alias of a script of the source in the config.
+    pub fn alias(&self, alias: &str) -> String {
+        format!("{}{}", self.prefix.as_deref().unwrap_or_default(), alias)
+    }
+
+    /// Clones the repository into the cache and checks out the ref, unless it was cloned before.
+    pub fn clone_if_missing(&self) -> PierResult<()> {
+        let dir = self.cache_dir()?;
+        if dir.exists() {
+            return Ok(());
+        }
+
+        let dir_arg = dir.to_string_lossy();
+        self.git(None, &["clone", "--quiet", "--no-checkout", "--", &self.git, &dir_arg])?;
+        // A clone without a checkout would count as cloned the next time, and the failed
+        // checkout is the error worth reporting.
+        if let Err(err) = self.checkout(&dir) {
+            let _ = fs::remove_dir_all(&dir);
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Fetches the new commits of the repository and checks out the ref again, then returns the
+    /// commit that is checked out.
+    pub fn update(&self) -> PierResult<String> {
+        let dir = self.cache_dir()?;
+        match dir.exists() {
+            true => {
+                self.git(Some(&dir), &["fetch", "--quiet", "--tags", "origin"])?;
+                self.checkout(&dir)?;
+            }
+            false => self.clone_if_missing()?,
+        }
+
+        self.git(Some(&dir), &["rev-parse", "--short", "HEAD"])
+    }
+
+    /// The config of the source as it is in the cache, with the aliases of the scripts prefixed
+    /// and the scripts marked as coming from the source.
+    pub fn load(&self) -> PierResult<Config> {
+        let path = self
+            .cache_dir()?
+            .join(self.path.as_deref().unwrap_or_else(|| Path::new(SOURCE_CONFIG_FILE)));
+        let store: Box<dyn ScriptStore> = match path.is_dir() {
+            true => Box::new(DirectoryStore::new(path)),
+            false => Box::new(TomlStore::new(path)),
+        };
+
+        let mut config = Config::default();
+        for (alias, script) in store.load()?.scripts.iter() {
+            let mut script = script.clone();
+            script.alias = self.alias(alias);
+            // Prerequisites are scripts of the same source, never the ones of the config.
+            if let Some(depends_on) = &mut script.depends_on {
+                for dependency in depends_on.iter_mut() {
+                    *dependency = self.alias(dependency);
+                }
+            }
+            script.source = Some(self.git.clone());
+            config.scripts.insert(script.alias.clone(), script);
+        }
+
+        Ok(config)
+    }
+
+    /// Checks out the ref as a detached head. Branches are taken from the remote, so they are at
+    /// the last fetched commit, other refs are tags or commits. Without a ref it's the default
+    /// branch of the remote.
+    fn checkout(&self, dir: &Path) -> PierResult<()> {
+        let commit = match &self.git_ref {
+            Some(git_ref) => {
+                let branch = format!("origin/{}^{{commit}}", git_ref);
+                match self.git(Some(dir), &["rev-parse", "--verify", "--quiet", &branch]) {
+                    Ok(commit) => commit,
+                    Err(_) => {
+                        let commit = format!("{}^{{commit}}", git_ref);
+                        self.git(Some(dir), &["rev-parse", "--verify", "--quiet", &commit])?
+                    }
+                }
+            }
+            None => self.git(Some(dir), &["rev-parse", "--verify", "--quiet", "origin/HEAD"])?,
+        };
+
+        self.git(Some(dir), &["checkout", "--quiet", "--detach", &commit])?;
+
+        Ok(())
+    }
+
+    /// Runs git in the directory and returns what it printed.
+    fn git(&self, dir: Option<&Path>, args: &[&str]) -> PierResult<String> {
+        let mut command = Command::new("git");
+        if let Some(dir) = dir {
+            command.arg("-C").arg(dir);
+        }
+        let output = command.args(args).output().context(SourceGitExec)?;
+        ensure!(
+            output.status.success(),
+            SourceGitFailed {
+                git: &self.git,
+                command: args.join(" "),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            }
+        );
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+/// Escapes the characters that aren't safe in a file name, and the escape character itself, as
+/// their bytes in hex so different names never end up the same.
+fn escape_file_name(name: &str) -> String {
+    let mut escaped = String::new();
+    for (index, c) in name.char_indices() {
+        match c.is_ascii_alphanumeric() || c == '-' || c == '_' || (c == '.' && index > 0) {
+            true => escaped.push(c),
+            false => {
+                let mut bytes = [0; 4];
+                for byte in c.encode_utf8(&mut bytes).bytes() {
+                    escaped.push_str(&format!("%{:02X}", byte));
+                }
+            }
+        }
+    }
+
+    escaped
+}
+
+/// Adds the scripts of the sources of the config, cloning the sources that aren't in the cache
+/// yet. Scripts of the config itself win over scripts of sources with the same alias, and earlier
+/// sources win over later ones.
+///
+/// A source that can't be cloned or read is skipped, so the config stays usable, and returned as
+/// an event to report.
+pub(crate) fn merge_sources(config: &mut Config) -> Vec<Event> {
+    let mut skipped = vec![];
+    for source in config.sources.clone() {
+        let source_config = match source.clone_if_missing().and_then(|_| source.load()) {
+            Ok(source_config) => source_config,
+            Err(err) => {
+                skipped.push(Event::SourceSkipped {
+                    git: source.git.clone(),
+                    error: err.to_string(),
+                });
+                continue;
+            }
+        };
+        for (alias, script) in source_config.scripts.iter() {
+            if !config.scripts.contains_key(alias) {
+                config.scripts.insert(alias.clone(), script.clone());
+            }
+        }
+    }
+
+    skipped
+}
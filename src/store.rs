@@ -0,0 +1,572 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, ResultExt};
+
+use super::config::{Config, ConfigDefaultOpts, Source};
+use super::defaults::*;
+use super::error::*;
+use super::script::{shebang_interpreter, Script};
+use super::PierResult;
+
+/// Where the scripts of pier are kept.
+///
+/// Only load and save are required, get, put and delete go through them by default. Backends
+/// which can reach a single script directly should override those.
+pub trait ScriptStore: Debug + Send + Sync {
+    /// Reads the whole config from the store.
+    fn load(&self) -> PierResult<Config>;
+
+    /// Replaces the whole config in the store.
+    fn save(&mut self, config: &Config) -> PierResult<()>;
+
+    /// The path of the store, which child pier processes are pointed to.
+    fn location(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Reads the script with the alias, none if there is no such script.
+    fn get(&self, alias: &str) -> PierResult<Option<Script>> {
+        Ok(self.load()?.scripts.get(alias).cloned())
+    }
+
+    /// Adds the script or replaces the script with the same alias.
+    fn put(&mut self, script: Script) -> PierResult<()> {
+        let mut config = self.load()?;
+        config.scripts.insert(script.alias.clone(), script);
+
+        self.save(&config)
+    }
+
+    /// Removes the script with the alias and returns it, none if there is no such script.
+    fn delete(&mut self, alias: &str) -> PierResult<Option<Script>> {
+        let mut config = self.load()?;
+        let script = config.scripts.remove(alias);
+        if script.is_some() {
+            self.save(&config)?;
+        }
+
+        Ok(script)
+    }
+}
+
+/// Keeps the config in a single TOML file.
+#[derive(Debug, Clone)]
+pub struct TomlStore {
+    path: PathBuf,
+}
+
+impl TomlStore {
+    pub fn new(path: PathBuf) -> Self {
+        TomlStore { path }
+    }
+}
+
+impl ScriptStore for TomlStore {
+    fn load(&self) -> PierResult<Config> {
+        Config::from(&self.path)
+    }
+
+    fn save(&mut self, config: &Config) -> PierResult<()> {
+        config.write(&self.path)
+    }
+
+    fn location(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct DirectoryDefaults {
+    #[serde(default)]
+    default: ConfigDefaultOpts,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sources: Vec<Source>,
+}
+
+/// The files of a script in a directory store.
+#[derive(Debug, Default)]
+struct ScriptFiles {
+    // The executable file holding the command, like flush-docker.sh.
+    script: Option<PathBuf>,
+    // The TOML file with the metadata, or the whole script when there is no executable file.
+    toml: Option<PathBuf>,
+}
+
+/// Keeps every script in a directory, named after its alias.
+///
+/// A script is either an executable file like `flush-docker.sh`, with its metadata in front
+/// matter comments or in a sidecar `flush-docker.toml`, or a TOML file on its own holding the
+/// whole script. The default options live in `.pier.toml`.
+#[derive(Debug, Clone)]
+pub struct DirectoryStore {
+    path: PathBuf,
+}
+
+impl DirectoryStore {
+    pub fn new(path: PathBuf) -> Self {
+        DirectoryStore { path }
+    }
+
+    /// Aliases in a group are kept in a directory of the group, so every part of the alias has
+    /// to be a name of a file.
+    fn check_alias(alias: &str) -> PierResult<()> {
+        ensure!(
+            alias
+                .split('/')
+                .all(|part| !part.is_empty() && !part.starts_with('.')),
+            InvalidScriptFileName { alias }
+        );
+
+        Ok(())
+    }
+
+    /// The files of every script in the directory by alias.
+    fn script_files(&self) -> PierResult<BTreeMap<String, ScriptFiles>> {
+        let mut files: BTreeMap<String, ScriptFiles> = BTreeMap::new();
+        DirectoryStore::group_files(&self.path, "", &mut files)?;
+
+        Ok(files)
+    }
+
+    /// Adds the files of the scripts in a directory of the store, with the subdirectories as
+    /// groups.
+    fn group_files(
+        dir: &Path,
+        group: &str,
+        files: &mut BTreeMap<String, ScriptFiles>,
+    ) -> PierResult<()> {
+        let entries = fs::read_dir(dir).context(ConfigRead { path: dir })?;
+
+        for entry in entries {
+            let path = entry.context(ConfigRead { path: dir })?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) if !name.starts_with('.') => name,
+                _ => continue,
+            };
+            if path.is_dir() {
+                DirectoryStore::group_files(&path, &format!("{}{}/", group, name), files)?;
+                continue;
+            }
+            let alias = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(stem) if path.is_file() => format!("{}{}", group, stem),
+                _ => continue,
+            };
+            let is_toml =
+                path.extension().and_then(|ext| ext.to_str()) == Some(SCRIPT_FILE_EXTENSION);
+            // Other files like a README can live next to the scripts.
+            if !is_toml && !is_script_file(&path)? {
+                continue;
+            }
+
+            let script_files = files.entry(alias.clone()).or_default();
+            if is_toml {
+                script_files.toml = Some(path);
+            } else {
+                if let Some(first) = &script_files.script {
+                    return DuplicateScriptFile {
+                        alias,
+                        first,
+                        second: path,
+                    }
+                    .fail();
+                }
+                script_files.script = Some(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_script(alias: &str, files: &ScriptFiles) -> PierResult<Script> {
+        let mut script = match (&files.script, &files.toml) {
+            (Some(path), toml_path) => {
+                let content = fs::read_to_string(path).context(ConfigRead { path })?;
+                let (front_matter, command) = split_front_matter(&content);
+                let mut script: Script = match toml_path {
+                    Some(toml_path) => {
+                        let metadata = fs::read_to_string(toml_path)
+                            .context(ConfigRead { path: toml_path })?;
+                        toml::from_str(&metadata).context(TomlParse { path: toml_path })?
+                    }
+                    None => {
+                        let metadata = front_matter.map(|(_, metadata)| metadata);
+                        toml::from_str(&metadata.unwrap_or_default()).context(TomlParse { path })?
+                    }
+                };
+                script.command = command;
+                script
+            }
+            (None, Some(path)) => {
+                let content = fs::read_to_string(path).context(ConfigRead { path })?;
+                toml::from_str(&content).context(TomlParse { path })?
+            }
+            (None, None) => unreachable!("Script files are found by their files"),
+        };
+        script.alias = alias.to_string();
+
+        Ok(script)
+    }
+
+    fn write_script(
+        &self,
+        alias: &str,
+        script: &Script,
+        files: Option<&ScriptFiles>,
+    ) -> PierResult<()> {
+        DirectoryStore::check_alias(alias)?;
+
+        let script_path = match files {
+            Some(ScriptFiles {
+                script: Some(path), ..
+            }) => Some(path.clone()),
+            Some(_) => None,
+            // New scripts get an executable file when the type of file is known from its
+            // interpreter, multi-step scripts and references are kept in TOML.
+            None if script.steps.is_none() && !script.command.is_empty() => {
+                script_extension(&script.command)
+                    .map(|extension| self.path.join(format!("{}.{}", alias, extension)))
+            }
+            None => None,
+        };
+
+        if let Some(parent_dir) = self.path.join(alias).parent() {
+            fs::create_dir_all(parent_dir).context(CreateDirectory)?;
+        }
+
+        let script_path = match script_path {
+            Some(script_path) => script_path,
+            None => {
+                let path = match files.and_then(|files| files.toml.clone()) {
+                    Some(path) => path,
+                    None => {
+                        self.path
+                            .join(format!("{}.{}", alias, SCRIPT_FILE_EXTENSION))
+                    }
+                };
+                let content = toml::to_string_pretty(script).context(TomlSerialize)?;
+                return write_if_changed(&path, &content);
+            }
+        };
+
+        let metadata = toml::to_string_pretty(&Script {
+            command: String::new(),
+            ..script.clone()
+        })
+        .context(TomlSerialize)?;
+
+        let content = match files.and_then(|files| files.toml.as_ref()) {
+            Some(toml_path) => {
+                write_if_changed(toml_path, &metadata)?;
+                script.command.clone()
+            }
+            None => {
+                let existing = fs::read_to_string(&script_path).unwrap_or_default();
+                let prefix = match split_front_matter(&existing) {
+                    (Some((prefix, _)), _) => prefix,
+                    _ => comment_prefix(&script_path).to_string(),
+                };
+                join_front_matter(&script.command, &prefix, &metadata)
+            }
+        };
+        write_if_changed(&script_path, &content)?;
+
+        let mut permissions = fs::metadata(&script_path)
+            .context(ConfigRead { path: &script_path })?
+            .permissions();
+        if permissions.mode() & 0o111 == 0 {
+            permissions.set_mode(permissions.mode() | 0o755);
+            fs::set_permissions(&script_path, permissions)
+                .context(ConfigWrite { path: &script_path })?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_script(&self, files: &ScriptFiles) -> PierResult<()> {
+        for path in files.script.iter().chain(files.toml.iter()) {
+            fs::remove_file(path).context(ConfigWrite { path })?;
+
+            // Directories of groups go away with their last script, removing fails otherwise.
+            let mut dir = path.parent();
+            while let Some(group_dir) = dir {
+                let is_group = group_dir.starts_with(&self.path) && group_dir != self.path;
+                if !is_group || fs::remove_dir(group_dir).is_err() {
+                    break;
+                }
+                dir = group_dir.parent();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ScriptStore for DirectoryStore {
+    fn load(&self) -> PierResult<Config> {
+        let mut config = Config::default();
+
+        let defaults_path = self.path.join(DIRECTORY_DEFAULTS_FILE);
+        if defaults_path.exists() {
+            let content = fs::read_to_string(&defaults_path).context(ConfigRead {
+                path: &defaults_path,
+            })?;
+            let defaults: DirectoryDefaults = toml::from_str(&content).context(TomlParse {
+                path: &defaults_path,
+            })?;
+            config.default = defaults.default;
+            config.sources = defaults.sources;
+        }
+
+        for (alias, files) in self.script_files()? {
+            let script = DirectoryStore::read_script(&alias, &files)?;
+            config.scripts.insert(alias, script);
+        }
+
+        Ok(config)
+    }
+
+    fn save(&mut self, config: &Config) -> PierResult<()> {
+        fs::create_dir_all(&self.path).context(CreateDirectory)?;
+
+        let files = self.script_files()?;
+        for (alias, script) in config.scripts.iter() {
+            self.write_script(alias, script, files.get(alias))?;
+        }
+        for (alias, files) in files.iter() {
+            if !config.scripts.contains_key(alias) {
+                self.remove_script(files)?;
+            }
+        }
+
+        // Only directories with default options or sources get a defaults file.
+        let defaults_path = self.path.join(DIRECTORY_DEFAULTS_FILE);
+        let defaults = toml::to_string_pretty(&config.default).context(TomlSerialize)?;
+        if !defaults.trim().is_empty() || !config.sources.is_empty() {
+            let content = toml::to_string_pretty(&DirectoryDefaults {
+                default: config.default.clone(),
+                sources: config.sources.clone(),
+            })
+            .context(TomlSerialize)?;
+            write_if_changed(&defaults_path, &content)?;
+        } else if defaults_path.exists() {
+            fs::remove_file(&defaults_path).context(ConfigWrite {
+                path: &defaults_path,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn location(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn get(&self, alias: &str) -> PierResult<Option<Script>> {
+        match self.script_files()?.get(alias) {
+            Some(files) => Ok(Some(DirectoryStore::read_script(alias, files)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put(&mut self, script: Script) -> PierResult<()> {
+        fs::create_dir_all(&self.path).context(CreateDirectory)?;
+
+        let files = self.script_files()?;
+        self.write_script(&script.alias, &script, files.get(&script.alias))
+    }
+
+    fn delete(&mut self, alias: &str) -> PierResult<Option<Script>> {
+        let files = match self.script_files()?.remove(alias) {
+            Some(files) => files,
+            None => return Ok(None),
+        };
+        let script = DirectoryStore::read_script(alias, &files)?;
+        self.remove_script(&files)?;
+
+        Ok(Some(script))
+    }
+}
+
+const FRONT_MATTER_MARKER: &str = "---";
+
+/// Splits the front matter off the content of a script file. Returns the comment prefix and the
+/// TOML of the front matter if there is any, and the command without it.
+///
+/// The front matter is a block of comments right after the shebang, between two `---` lines:
+///
+/// ```sh
+/// #!/bin/sh
+/// # ---
+/// # description = "Clears out old Docker containers and images"
+/// # tags = ["docker"]
+/// # ---
+/// docker system prune -a -f
+/// ```
+fn split_front_matter(content: &str) -> (Option<(String, String)>, String) {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let start = match lines.first() {
+        Some(line) if line.starts_with("#!") => 1,
+        _ => 0,
+    };
+
+    let prefix = match lines.get(start).map(|line| line.trim()) {
+        Some(line) if line.ends_with(FRONT_MATTER_MARKER) => {
+            line.trim_end_matches(FRONT_MATTER_MARKER).trim()
+        }
+        _ => return (None, content.to_string()),
+    };
+    if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_punctuation()) {
+        return (None, content.to_string());
+    }
+
+    let marker = lines[start].trim();
+    let end = match lines[start + 1..]
+        .iter()
+        .position(|line| line.trim() == marker)
+    {
+        Some(position) => start + 1 + position,
+        None => return (None, content.to_string()),
+    };
+
+    let metadata = lines[start + 1..end]
+        .iter()
+        .map(|line| {
+            let line = line.trim_start().trim_start_matches(prefix);
+            line.strip_prefix(' ').unwrap_or(line)
+        })
+        .collect();
+    let command = lines[..start]
+        .iter()
+        .chain(lines[end + 1..].iter())
+        .copied()
+        .collect();
+
+    (Some((prefix.to_string(), metadata)), command)
+}
+
+/// Puts the metadata as front matter into the command, after the shebang if it has one.
+fn join_front_matter(command: &str, prefix: &str, metadata: &str) -> String {
+    if metadata.trim().is_empty() {
+        return command.to_string();
+    }
+
+    let (shebang, body) = match command.starts_with("#!") {
+        true => command.split_at(command.find('\n').map_or(command.len(), |end| end + 1)),
+        false => ("", command),
+    };
+    let mut content = shebang.to_string();
+    if !shebang.is_empty() && !shebang.ends_with('\n') {
+        content.push('\n');
+    }
+
+    content.push_str(&format!("{} {}\n", prefix, FRONT_MATTER_MARKER));
+    for line in metadata.lines() {
+        match line.is_empty() {
+            true => content.push_str(&format!("{}\n", prefix)),
+            false => content.push_str(&format!("{} {}\n", prefix, line)),
+        }
+    }
+    content.push_str(&format!("{} {}\n", prefix, FRONT_MATTER_MARKER));
+    content.push_str(body);
+
+    content
+}
+
+/// Whether the file holds a script, which is known from its extension or the executable bit.
+fn is_script_file(path: &Path) -> PierResult<bool> {
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    if let Some("sh" | "bash" | "zsh" | "py" | "js" | "rb" | "pl") = extension {
+        return Ok(true);
+    }
+    let metadata = fs::metadata(path).context(ConfigRead { path })?;
+
+    Ok(metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// The file extension of a new script, guessed from the interpreter in its shebang. Commands
+/// without a shebang run in the shell.
+fn script_extension(command: &str) -> Option<&'static str> {
+    if !command.starts_with("#!") {
+        return Some("sh");
+    }
+
+    let interpreter = shebang_interpreter(command)?;
+    match interpreter {
+        "sh" | "bash" | "dash" | "zsh" | "ksh" => Some("sh"),
+        "node" => Some("js"),
+        "ruby" => Some("rb"),
+        "perl" => Some("pl"),
+        _ if interpreter.starts_with("python") => Some("py"),
+        _ => None,
+    }
+}
+
+fn comment_prefix(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("js") => "//",
+        _ => "#",
+    }
+}
+
+/// Leaves files alone that already have the content, which keeps their modification times.
+fn write_if_changed(path: &Path, content: &str) -> PierResult<()> {
+    if fs::read_to_string(path).map_or(false, |existing| existing == content) {
+        return Ok(());
+    }
+
+    fs::write(path, content).context(ConfigWrite { path })
+}
+
+/// Keeps the config in memory only, for tests and tools which bring their own scripts.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    config: Config,
+}
+
+impl MemoryStore {
+    pub fn new(config: Config) -> Self {
+        MemoryStore { config }
+    }
+
+    /// A store holding the scripts, with the aliases taken from the scripts.
+    pub fn from_scripts(scripts: Vec<Script>) -> Self {
+        let mut config = Config::default();
+        for script in scripts {
+            config.scripts.insert(script.alias.clone(), script);
+        }
+
+        MemoryStore { config }
+    }
+}
+
+impl ScriptStore for MemoryStore {
+    fn load(&self) -> PierResult<Config> {
+        Ok(self.config.clone())
+    }
+
+    fn save(&mut self, config: &Config) -> PierResult<()> {
+        self.config = config.clone();
+
+        Ok(())
+    }
+
+    fn get(&self, alias: &str) -> PierResult<Option<Script>> {
+        Ok(self.config.scripts.get(alias).cloned())
+    }
+
+    fn put(&mut self, script: Script) -> PierResult<()> {
+        self.config.scripts.insert(script.alias.clone(), script);
+
+        Ok(())
+    }
+
+    fn delete(&mut self, alias: &str) -> PierResult<Option<Script>> {
+        Ok(self.config.scripts.remove(alias))
+    }
+}
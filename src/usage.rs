@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Local};
+
+use super::history::RunRecord;
+
+/// The order in which scripts are listed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Name,
+    Frecency,
+    LastRun,
+    Count,
+}
+
+impl SortOrder {
+    pub const VARIANTS: &'static [&'static str] = &["name", "frecency", "last-run", "count"];
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(sort: &str) -> Result<Self, Self::Err> {
+        match sort {
+            "name" => Ok(SortOrder::Name),
+            "frecency" => Ok(SortOrder::Frecency),
+            "last-run" => Ok(SortOrder::LastRun),
+            "count" => Ok(SortOrder::Count),
+            _ => {
+                Err(format!(
+                    "Unknown sort order {}, expected one of {}",
+                    sort,
+                    SortOrder::VARIANTS.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+/// How often and how recently a script has been run.
+#[derive(Debug, Clone, Default)]
+pub struct Usage {
+    pub count: usize,
+    pub last_run: Option<DateTime<Local>>,
+    // Every run adds a weight which gets smaller the longer ago the run was.
+    pub frecency: f64,
+}
+
+/// Sums up the usage of every alias in the run history.
+pub fn usage_by_alias(records: &[RunRecord]) -> BTreeMap<String, Usage> {
+    let now = Local::now();
+    let mut usages: BTreeMap<String, Usage> = BTreeMap::new();
+
+    for record in records {
+        let usage = usages.entry(record.alias.clone()).or_default();
+        usage.count += 1;
+        usage.frecency += recency_weight(now - record.timestamp);
+        if usage
+            .last_run
+            .map_or(true, |last_run| last_run < record.timestamp)
+        {
+            usage.last_run = Some(record.timestamp);
+        }
+    }
+
+    usages
+}
+
+fn recency_weight(age: Duration) -> f64 {
+    if age < Duration::hours(1) {
+        4.0
+    } else if age < Duration::days(1) {
+        2.0
+    } else if age < Duration::weeks(1) {
+        1.0
+    } else if age < Duration::weeks(4) {
+        0.5
+    } else {
+        0.25
+    }
+}
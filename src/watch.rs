@@ -0,0 +1,243 @@
+use std::env;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::Duration;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+
+use super::defaults::*;
+use super::error::*;
+use super::PierResult;
+
+/// Settings for re-running a script when files change.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct WatchOpts {
+    // The files and directories to watch, defaults to the current directory.
+    pub paths: Option<Vec<PathBuf>>,
+    // Only changes to files matching one of these globs trigger a run.
+    pub globs: Option<Vec<String>>,
+    // Changes to files matching one of these globs never trigger a run.
+    pub ignore: Option<Vec<String>>,
+    // Clear the screen before every run.
+    pub clear: Option<bool>,
+    // How long to wait for more changes before running the script.
+    pub debounce_ms: Option<u64>,
+    // Skip changes to files ignored by the .gitignore, enabled unless set to false.
+    pub gitignore: Option<bool>,
+}
+
+impl WatchOpts {
+    /// Combines the options given on the commandline with the options of the script. Paths and
+    /// globs given on the commandline replace the ones of the script, ignore patterns are added.
+    pub fn merge(self, script: Option<&WatchOpts>) -> WatchOpts {
+        let script = script.cloned().unwrap_or_default();
+        let ignore = match (script.ignore, self.ignore) {
+            (Some(mut script_ignore), Some(ignore)) => {
+                script_ignore.extend(ignore);
+                Some(script_ignore)
+            }
+            (script_ignore, ignore) => ignore.or(script_ignore),
+        };
+
+        WatchOpts {
+            paths: self.paths.or(script.paths),
+            globs: self.globs.or(script.globs),
+            ignore,
+            clear: self.clear.or(script.clear),
+            debounce_ms: self.debounce_ms.or(script.debounce_ms),
+            gitignore: self.gitignore.or(script.gitignore),
+        }
+    }
+}
+
+/// Decides which changed files trigger a run.
+struct ChangeFilter {
+    root: PathBuf,
+    globs: Option<GlobSet>,
+    ignore: GlobSet,
+    gitignore: Option<Gitignore>,
+}
+
+impl ChangeFilter {
+    fn new(opts: &WatchOpts, paths: &[PathBuf]) -> PierResult<Self> {
+        let root = env::current_dir()
+            .and_then(|dir| dir.canonicalize())
+            .context(CommandExec)?;
+
+        let globs = match &opts.globs {
+            Some(globs) => Some(glob_set(globs)?),
+            None => None,
+        };
+
+        let gitignore = match opts.gitignore.unwrap_or(true) {
+            true => {
+                let mut builder = GitignoreBuilder::new(&root);
+                for dir in std::iter::once(&root).chain(paths.iter()) {
+                    let file = dir.join(".gitignore");
+                    if file.exists() {
+                        if let Some(err) = builder.add(file) {
+                            return Err(err).context(GitignoreParse);
+                        }
+                    }
+                }
+                Some(builder.build().context(GitignoreParse)?)
+            }
+            false => None,
+        };
+
+        Ok(ChangeFilter {
+            root,
+            globs,
+            ignore: glob_set(opts.ignore.as_deref().unwrap_or_default())?,
+            gitignore,
+        })
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+
+        if relative.components().any(|part| part.as_os_str() == ".git") {
+            return false;
+        }
+        if self.ignore.is_match(relative) {
+            return false;
+        }
+        if let Some(gitignore) = &self.gitignore {
+            if path.starts_with(&self.root)
+                && gitignore
+                    .matched_path_or_any_parents(relative, path.is_dir())
+                    .is_ignore()
+            {
+                return false;
+            }
+        }
+
+        match &self.globs {
+            Some(globs) => globs.is_match(relative),
+            None => true,
+        }
+    }
+
+    fn is_relevant(&self, event: &Event) -> bool {
+        match event.kind {
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
+                event.paths.iter().any(|path| self.matches(path))
+            }
+            _ => false,
+        }
+    }
+}
+
+fn glob_set(globs: &[String]) -> PierResult<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).context(InvalidGlob { glob })?);
+    }
+
+    builder.build().context(InvalidGlob {
+        glob: globs.join(","),
+    })
+}
+
+/// Runs the command every time a watched file changes. A run that is still going when files
+/// change again is terminated before the next run starts.
+pub fn watch(mut run_command: impl FnMut() -> Command, opts: &WatchOpts) -> PierResult<()> {
+    let paths = match &opts.paths {
+        Some(paths) if !paths.is_empty() => paths.clone(),
+        _ => vec![PathBuf::from(".")],
+    };
+    let filter = ChangeFilter::new(opts, &paths)?;
+    let debounce = Duration::from_millis(opts.debounce_ms.unwrap_or(WATCH_DEBOUNCE_MS));
+    let clear = opts.clear.unwrap_or(false);
+
+    let (sender, receiver) = channel();
+    let mut watcher = notify::recommended_watcher(sender).context(WatchFiles)?;
+    for path in &paths {
+        watcher
+            .watch(path, RecursiveMode::Recursive)
+            .context(WatchFiles)?;
+    }
+
+    // The runs don't belong to the process group of the terminal, so pier has to stop the
+    // current run itself when it is interrupted.
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    unsafe {
+        libc::signal(libc::SIGINT, interrupt as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, interrupt as libc::sighandler_t);
+    }
+
+    let mut child = Some(start(&mut run_command, clear)?);
+
+    loop {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            if let Some(current) = child.take() {
+                stop(current)?;
+            }
+            return Ok(());
+        }
+
+        let event = match receiver.recv_timeout(Duration::from_millis(WATCH_POLL_INTERVAL_MS)) {
+            Ok(event) => event.context(WatchFiles)?,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        if !filter.is_relevant(&event) {
+            continue;
+        }
+
+        // Wait until the files have been quiet for a while, so a burst of changes results in a
+        // single run.
+        loop {
+            match receiver.recv_timeout(debounce) {
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+
+        if let Some(previous) = child.take() {
+            stop(previous)?;
+        }
+        child = Some(start(&mut run_command, clear)?);
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+fn start(run_command: &mut impl FnMut() -> Command, clear: bool) -> PierResult<Child> {
+    if clear {
+        // Clears the screen and moves the cursor to the top left corner.
+        print!("\x1B[2J\x1B[H");
+        io::stdout().flush().context(CommandExec)?;
+    }
+
+    run_command()
+        .stdin(Stdio::null())
+        .spawn()
+        .context(CommandExec)
+}
+
+/// Terminates the process group of a run that hasn't finished yet.
+fn stop(mut child: Child) -> PierResult<()> {
+    if child.try_wait().context(CommandExec)?.is_none() {
+        // The run was started in its own process group, which also holds the processes of the
+        // script.
+        unsafe {
+            libc::kill(-(child.id() as i32), libc::SIGTERM);
+        }
+    }
+    child.wait().context(CommandExec)?;
+
+    Ok(())
+}
@@ -2,9 +2,13 @@ use assert_cmd::prelude::*;
 use assert_fs::fixture::ChildPath;
 use assert_fs::prelude::*;
 use predicates::prelude::*;
-use predicates::str::contains;
+use pier::jobs::{Job, Jobs};
+use pier::{script::Script, store::MemoryStore, Pier, RunOptions};
+use std::path::PathBuf;
+use predicates::str::{contains, starts_with};
 use std::process::Command;
 
+#[allow(clippy::redundant_static_lifetimes)]
 const CONFIG_1: &'static str = r#"
 [scripts.test_cmd_1]
 alias = 'test_cmd_1'
@@ -140,6 +144,80 @@ pier_test!(basic => test_config_initialization,
         cfg.assert(predicate::path::exists());
 });
 
+// Tests using a directory with a file for every script instead of a config file
+pier_test!(basic => test_directory_store,
+| te: crate::common::TestEnv | {
+    let scripts = te.dir.child("scripts");
+    scripts.child("hello.toml").write_str("command = 'echo hello_from_file'\n").unwrap();
+
+    let pier = |args: &[&str]| crate::common::store_cmd(&te, &scripts, args);
+
+    pier(&["run", "hello"]).assert()
+        .success()
+        .stdout(contains("hello_from_file"));
+
+    pier(&["add", "echo bye", "-a", "bye", "-d", "Says bye"]).assert().success();
+    scripts.child("bye.sh").assert("# ---\n# description = 'Says bye'\n# ---\necho bye");
+
+    pier(&["add", "echo flushed", "-a", "docker/flush"]).assert().success();
+    scripts.child("docker/flush.sh").assert("echo flushed");
+    pier(&["run", "flush"]).assert()
+        .success()
+        .stdout(contains("flushed"));
+
+    pier(&["rm", "hello"]).assert().success();
+    scripts.child("hello.toml").assert(predicate::path::missing());
+    pier(&["list", "-q", "--sort", "name"]).assert()
+        .success()
+        .stdout("bye\ndocker/flush\n");
+
+    pier(&["rm", "docker/flush"]).assert().success();
+    scripts.child("docker").assert(predicate::path::missing());
+});
+
+// Tests scripts kept as executable files with front matter or a sidecar file for the metadata
+pier_test!(basic => test_directory_store_script_files,
+| te: crate::common::TestEnv | {
+    let scripts = te.dir.child("scripts");
+    scripts.child("flush.sh").write_str(trim!(r#"
+        #!/bin/sh
+        # ---
+        # tags = ["docker"]
+        # ---
+        echo flushed
+    "#)).unwrap();
+    scripts.child("greet.sh").write_str("echo \"hello $1\"\n").unwrap();
+    scripts.child("greet.toml").write_str("tags = ['docker', 'hello']\n").unwrap();
+    scripts.child("README.md").write_str("# Scripts\n").unwrap();
+    scripts.child("flush.txt").write_str("Notes about flush\n").unwrap();
+    crate::common::write_executable(scripts.child("prune").path(), "#!/bin/sh\necho pruned\n");
+
+    let pier = |args: &[&str]| crate::common::store_cmd(&te, &scripts, args);
+
+    pier(&["list", "-q", "--sort", "name"]).assert()
+        .success()
+        .stdout("flush\ngreet\nprune\n");
+    pier(&["list", "-q", "-t", "docker"]).assert()
+        .success()
+        .stdout("flush\ngreet\n");
+    pier(&["show", "flush"]).assert()
+        .success()
+        .stdout("#!/bin/sh\necho flushed\n");
+    pier(&["run", "greet", "--", "pier"]).assert()
+        .success()
+        .stdout("hello pier\n");
+
+    pier(&["move", "greet", "welcome"]).assert().success();
+    scripts.child("greet.sh").assert(predicate::path::missing());
+    scripts.child("welcome.sh").assert(contains("# tags = [\n#     'docker',"));
+
+    scripts.child("flush.py").write_str("#!/usr/bin/env python3\nprint('flushed')\n").unwrap();
+    pier(&["list"]).assert()
+        .failure()
+        .stderr(contains("DuplicateScriptFile"))
+        .stderr(contains("flush.py"));
+});
+
 // Tests listing all aliases
 pier_test!(cli => test_list_aliases, cfg => CONFIG_1,
 | _cfg: ChildPath, mut cmd: Command | {
@@ -304,6 +382,274 @@ pier_test!(cli => test_run_script, cfg => CONFIG_1,
         .stdout(contains("test_1"));
 });
 
+// Tests that running a script through the library captures its output instead of printing it
+pier_test!(lib => test_run_script_captures_output, cfg => CONFIG_1,
+| _cfg: ChildPath, lib: Pier | {
+    let outcome = lib
+        .run_script("test_cmd_1", vec![], RunOptions::default())
+        .expect("Failed to run script");
+    assert!(outcome.status.success());
+    assert_eq!(outcome.stdout, b"test_1\n");
+    assert!(outcome.stderr.is_empty());
+});
+
+// Tests that scripts can be kept in memory by embedding tools
+#[test]
+fn test_memory_store() {
+    let store = MemoryStore::from_scripts(vec![Script {
+        alias: "in_memory".to_string(),
+        command: "echo in_memory".to_string(),
+        ..Script::default()
+    }]);
+    let mut lib = Pier::with_store(Box::new(store), false).expect("Failed to load store");
+
+    lib.copy_script("in_memory", "copied").expect("Failed to copy script");
+    lib.write().expect("Failed to save scripts");
+
+    let outcome = lib
+        .run_script("copied", vec![], RunOptions::default())
+        .expect("Failed to run script");
+    assert_eq!(outcome.stdout, b"in_memory\n");
+}
+
+// Tests running, showing and listing scripts which reference an existing file
+pier_test!(cli => test_reference_script, cfg => r#"
+[scripts.greet]
+reference = 'bin/greet.sh'
+
+[scripts.gone]
+reference = 'bin/gone.sh'
+"#, | cfg: ChildPath, mut cmd: Command | {
+    let root = cfg.path().parent().unwrap();
+    crate::common::write_executable(&root.join("bin/greet.sh"), "#!/bin/sh\necho \"hello $1\"\n");
+
+    cmd.args(&["run", "greet", "--", "pier"]);
+    cmd.assert()
+        .success()
+        .stdout("hello pier\n");
+
+    crate::common::cli_cmd(&cfg).args(&["show", "greet"]).assert()
+        .success()
+        .stdout("#!/bin/sh\necho \"hello $1\"\n");
+
+    crate::common::cli_cmd(&cfg).args(&["list"]).assert()
+        .success()
+        .stdout(contains("missing: bin/gone.sh"));
+
+    crate::common::cli_cmd(&cfg).args(&["run", "gone"]).assert()
+        .failure()
+        .stderr(contains("ReferenceNotFound"));
+});
+
+// Tests importing the executables of a directory after reviewing each one
+pier_test!(cli => test_import_dir, cfg => CONFIG_1,
+| cfg: ChildPath, mut cmd: Command | {
+    let root = cfg.path().parent().unwrap();
+    crate::common::write_executable(&root.join("bin/backup"), trim!(r#"
+        #!/usr/bin/env python3
+        # -*- coding: utf-8 -*-
+        # Backs up the
+        # home directory.
+        #
+        # Usage: backup
+        print("backup")
+    "#));
+    crate::common::write_executable(&root.join("bin/deploy/release.sh"), trim!(r#"
+        #!/bin/bash
+        # Releases the app
+        echo release
+    "#));
+    crate::common::write_executable(&root.join("bin/.hidden"), "echo hidden\n");
+    std::fs::write(root.join("bin/notes.txt"), "not a script\n").unwrap();
+
+    let mut child = cmd
+        .args(&["import", "dir", "bin"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to start pier import");
+    {
+        use std::io::Write;
+        let mut stdin = child.stdin.take().expect("Import stdin is piped");
+        stdin.write_all(b"y\nn\n").expect("Failed to answer");
+    }
+    let output = child.wait_with_output().expect("Failed to run pier import");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Imported 1 scripts"));
+
+    cfg.assert(contains(trim!(r#"
+        [scripts.backup]
+        description = 'Backs up the home directory.'
+    "#)).trim());
+    cfg.assert(contains("tags = ['python']"));
+    cfg.assert(contains("release").not());
+    cfg.assert(contains("hidden").not());
+    cfg.assert(contains("notes").not());
+
+    crate::common::cli_cmd(&cfg).args(&["import", "dir", "bin", "--yes", "--inline"]).assert()
+        .success()
+        .stdout(contains("Skipped backup, the alias already exists"))
+        .stdout(contains("Imported 1 scripts"));
+    cfg.assert(contains("[scripts.release]\ncommand = '''\n#!/bin/bash\n# Releases the app"));
+    cfg.assert(contains("description = 'Releases the app'\ntags = [\n    'deploy',\n    'bash',\n]"));
+
+    crate::common::cli_cmd(&cfg).args(&["backup"]).assert()
+        .success()
+        .stdout("backup\n");
+});
+
+// Tests importing the long and often repeated commands of a zsh history
+pier_test!(cli => test_import_history, cfg => CONFIG_1,
+| cfg: ChildPath, mut cmd: Command | {
+    let history = cfg.path().parent().unwrap().join("zsh_history");
+    std::fs::write(&history, trim!(r#"
+        : 1690000000:0;ls -la
+        : 1690000001:0;sudo docker system prune -a -f
+        : 1690000002:0;git status
+        : 1690000003:0;for file in *.txt; do\
+        echo $file\
+        done
+        : 1690000004:0;sudo docker system prune -a -f
+        : 1690000005:0;cd /tmp
+    "#)).unwrap();
+
+    cmd.args(&["import", "history", "--shell", "zsh", "--file", history.to_str().unwrap()]);
+    cmd.args(&["--limit", "2", "--yes"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("Imported 2 scripts"));
+
+    cfg.assert(contains("[scripts.docker-system-prune]\ncommand = 'sudo docker system prune -a -f'"));
+    cfg.assert(contains("[scripts.for-file-in]\ncommand = '''\nfor file in *.txt; do\necho $file\ndone'''"));
+    cfg.assert(contains("git status").not());
+    cfg.assert(contains("ls -la").not());
+});
+
+// Tests picking a different alias while reviewing the commands of a fish history
+pier_test!(cli => test_import_history_rename, cfg => CONFIG_1,
+| cfg: ChildPath, mut cmd: Command | {
+    let history = cfg.path().parent().unwrap().join("fish_history");
+    std::fs::write(&history, trim!(r#"
+        - cmd: kubectl rollout restart deployment/app
+          when: 1690000000
+        - cmd: echo one\necho two
+          when: 1690000001
+    "#)).unwrap();
+
+    let mut child = cmd
+        .args(&["import", "history", "--shell", "fish", "--file", history.to_str().unwrap()])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to start pier import");
+    {
+        use std::io::Write;
+        let mut stdin = child.stdin.take().expect("Import stdin is piped");
+        stdin.write_all(b"restart-app\nq\n").expect("Failed to answer");
+    }
+    let output = child.wait_with_output().expect("Failed to run pier import");
+    assert!(output.status.success());
+
+    cfg.assert(contains("[scripts.restart-app]\ncommand = 'kubectl rollout restart deployment/app'"));
+    cfg.assert(contains("echo one").not());
+});
+
+// Tests adding the previous command of the shell history
+pier_test!(cli => test_add_last, cfg => CONFIG_1,
+| cfg: ChildPath, mut cmd: Command | {
+    let history = cfg.path().parent().unwrap().join("bash_history");
+    std::fs::write(&history, "#1690000000\ngit log --oneline --graph\n#1690000001\npier add --last -a graph\n").unwrap();
+
+    cmd.env("SHELL", "/bin/bash").env("HISTFILE", &history);
+    cmd.args(&["add", "--last", "-a", "graph"]);
+    cmd.assert()
+        .success()
+        .stdout("Added graph\n");
+
+    cfg.assert(contains("[scripts.graph]\ncommand = 'git log --oneline --graph'"));
+});
+
+// Tests importing the targets of a Makefile, which run in the directory of the project
+pier_test!(cli => test_import_make, cfg => CONFIG_1,
+| cfg: ChildPath, mut cmd: Command | {
+    let project = cfg.path().parent().unwrap().join("app");
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(project.join("Makefile"), [
+        "NAME := app",
+        "FILES = a:b",
+        ".PHONY: build test",
+        "",
+        "build: ## Builds the app",
+        "\t@echo building $(NAME) in $$(basename $$(pwd))",
+        "",
+        "# Runs the tests",
+        "test: build",
+        "\t@echo testing",
+        "",
+        "%.o: %.c",
+        "\t@echo compiling",
+        "",
+    ].join("\n")).unwrap();
+
+    cmd.args(&["import", "make", project.join("Makefile").to_str().unwrap(), "--yes"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("Imported 2 scripts"));
+
+    cfg.assert(contains(format!(
+        "[scripts.app-build]\ncommand = 'make build'\ndescription = 'Builds the app'\nreference = '{}'\ncwd = '{}'\ntags = ['make']",
+        project.join("Makefile").display(),
+        project.display(),
+    )));
+    cfg.assert(contains("[scripts.app-test]\ncommand = 'make test'\ndescription = 'Runs the tests'"));
+    cfg.assert(contains(".o").not());
+
+    crate::common::cli_cmd(&cfg).args(&["run", "app-build"]).assert()
+        .success()
+        .stdout("building app in app\n");
+});
+
+// Tests importing the recipes of a justfile and the scripts of a package.json
+pier_test!(cli => test_import_just_npm, cfg => CONFIG_1,
+| cfg: ChildPath, mut cmd: Command | {
+    let root = cfg.path().parent().unwrap();
+    std::fs::write(root.join("justfile"), [
+        "set shell := [\"bash\", \"-c\"]",
+        "version := \"1.0\"",
+        "",
+        "# Runs the tests",
+        "test *args:",
+        "    cargo test {{args}}",
+        "",
+        "_helper:",
+        "    echo helper",
+        "",
+        "[private]",
+        "hidden:",
+        "    echo hidden",
+        "",
+        "@release: test",
+        "    echo {{version}}",
+        "",
+    ].join("\n")).unwrap();
+    std::fs::write(root.join("package.json"), r#"{"name": "app", "scripts": {"start": "node index.js"}}"#).unwrap();
+
+    cmd.args(&["import", "just", "--prefix", "", "--yes"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("Imported 2 scripts"));
+    cfg.assert(contains("[scripts.test]\ncommand = 'just test \"$@\"'\ndescription = 'Runs the tests'"));
+    cfg.assert(contains("[scripts.release]\ncommand = 'just release \"$@\"'"));
+    cfg.assert(contains("helper").not());
+    cfg.assert(contains("hidden").not());
+
+    crate::common::cli_cmd(&cfg).args(&["import", "npm", "-p", "web", "--yes"]).assert()
+        .success()
+        .stdout(contains("Imported 1 scripts"));
+    cfg.assert(contains("[scripts.web-start]\ncommand = 'npm run start -- \"$@\"'\ndescription = 'node index.js'"));
+    cfg.assert(contains("tags = ['npm']"));
+});
+
 // Tests running a script with a pipe in it
 pier_test!(cli => test_run_script_pipe, cfg => r#"
 [scripts.test_pipe]
@@ -394,3 +740,1472 @@ print("Running python script with interpreter!")
         .success()
         .stdout(contains("Running python script with interpreter!"));
 });
+
+// Tests that prerequisites run once and in topological order before the script.
+pier_test!(cli => test_run_with_dependencies, cfg => r#"
+[scripts.build]
+command = 'echo build-step'
+
+[scripts.migrate]
+command = 'echo migrate-step'
+depends_on = ['build']
+
+[scripts.deploy]
+command = 'echo deploy-step'
+depends_on = ['build', 'migrate']
+"#, | _cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["run", "deploy"])
+    .env("SHELL", "/bin/sh");
+    cmd.assert()
+        .success()
+        .stdout(contains("build-step\nmigrate-step\ndeploy-step"))
+        .stdout(contains("build-step").count(1));
+});
+
+// Tests that a failing prerequisite stops the script from running.
+pier_test!(cli => test_run_with_failing_dependency, cfg => r#"
+[scripts.broken]
+command = 'exit 3'
+
+[scripts.deploy]
+command = 'echo deploy-step'
+depends_on = ['broken']
+"#, | _cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["run", "deploy", "--parallel"])
+    .env("SHELL", "/bin/sh");
+    cmd.assert()
+        .failure()
+        .code(3)
+        .stdout(contains("deploy-step").not());
+});
+
+// Tests that steps run in order with their own interpreter, cwd and env.
+pier_test!(cli => test_run_steps, cfg => r#"
+[scripts.multi]
+
+[[scripts.multi.steps]]
+command = 'echo "step-one-$STEP_VAR"'
+
+[scripts.multi.steps.env]
+STEP_VAR = 'env'
+
+[[scripts.multi.steps]]
+command = 'print("step-two-python")'
+interpreter = ['python3', '-c']
+
+[[scripts.multi.steps]]
+command = 'pwd'
+cwd = '/'
+"#, | _cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["run", "multi"])
+    .env("SHELL", "/bin/sh");
+    cmd.assert()
+        .success()
+        .stdout(contains("step-one-env\nstep-two-python\n/\n"));
+});
+
+// Tests that steps stop at the first failure unless the step allows errors.
+pier_test!(cli => test_run_steps_failure, cfg => r#"
+[scripts.multi]
+
+[[scripts.multi.steps]]
+command = 'exit 1'
+continue_on_error = true
+
+[[scripts.multi.steps]]
+command = 'exit 4'
+
+[[scripts.multi.steps]]
+command = 'echo unreachable-step'
+"#, | _cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["run", "multi"])
+    .env("SHELL", "/bin/sh");
+    cmd.assert()
+        .failure()
+        .code(4)
+        .stdout(contains("unreachable-step").not());
+});
+
+// Tests that default and script hooks run around the script with the run details.
+pier_test!(cli => test_run_with_hooks, cfg => r#"
+[default.hooks]
+before = 'echo "global-before $PIER_ALIAS $PIER_ARGS"'
+after = 'echo "global-after $PIER_EXIT_CODE"'
+
+[scripts.failing]
+command = 'exit 2'
+
+[scripts.failing.hooks]
+on_success = 'echo script-success'
+on_failure = 'echo "script-failure $PIER_EXIT_CODE"'
+"#, | _cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["run", "failing", "first", "second"])
+    .env("SHELL", "/bin/sh");
+    cmd.assert()
+        .failure()
+        .code(2)
+        .stdout(contains("global-before failing first second\nscript-failure 2\nglobal-after 2"))
+        .stdout(contains("script-success").not());
+});
+
+// Tests that the hooks get the exit code of a signal and run when the script can't be started.
+pier_test!(cli => test_run_with_hooks_after_signal_and_error, cfg => r#"
+[default.hooks]
+after = 'echo "global-after [$PIER_EXIT_CODE]"'
+
+[scripts.killed]
+command = 'kill -TERM $$'
+
+[scripts.broken]
+command = '''#!/nonexistent/interpreter
+echo unreachable'''
+
+[scripts.broken.hooks]
+on_failure = 'echo broken-cleanup'
+"#, | cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["run", "killed"])
+    .env("SHELL", "/bin/sh");
+    cmd.assert()
+        .failure()
+        .code(143)
+        .stdout(contains("global-after [143]"));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["run", "broken"])
+        .env("SHELL", "/bin/sh")
+        .assert()
+        .failure()
+        .stdout(contains("broken-cleanup\nglobal-after []"))
+        .stdout(contains("unreachable").not());
+});
+
+// Tests that a failing before hook stops the script from running.
+pier_test!(cli => test_run_with_failing_before_hook, cfg => r#"
+[scripts.guarded]
+command = 'echo guarded-script'
+
+[scripts.guarded.hooks]
+before = 'exit 5'
+"#, | _cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["run", "guarded"])
+    .env("SHELL", "/bin/sh");
+    cmd.assert()
+        .failure()
+        .code(5)
+        .stdout(contains("guarded-script").not());
+});
+
+// Tests that runs stopped by a hook and runs that couldn't start are in the history.
+pier_test!(cli => test_run_history_of_unfinished_runs, cfg => r#"
+[scripts.guarded]
+command = 'echo guarded-script'
+
+[scripts.guarded.hooks]
+before = 'exit 5'
+
+[scripts.broken]
+command = '''#!/nonexistent/interpreter
+echo unreachable'''
+"#, | cfg: ChildPath, mut cmd: Command | {
+    cmd.args(["run", "guarded"])
+    .env("SHELL", "/bin/sh");
+    cmd.assert().failure().code(5);
+
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "broken"])
+        .assert()
+        .failure();
+
+    crate::common::cli_cmd(&cfg)
+        .args(["runs", "--failed"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"guarded[^\n]*⋮ \S*5 ").unwrap())
+        .stdout(predicate::str::is_match(r"broken[^\n]*⋮ \S*error").unwrap());
+});
+
+// Tests that runs are recorded in the history and can be run again.
+pier_test!(cli => test_run_history, cfg => CONFIG_1,
+| cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["run", "shebang-with-args", "first", "second"]);
+    cmd.assert().success();
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["run", "test_fail"])
+        .assert()
+        .failure();
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["runs", "--failed"])
+        .assert()
+        .success()
+        .stdout(contains("test_fail"))
+        .stdout(contains("shebang-with-args").not());
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["history", "shebang-with-args"])
+        .assert()
+        .success()
+        .stdout(contains("first second"));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["runs", "--rerun", "1"])
+        .assert()
+        .success()
+        .stdout(contains("first--second"));
+});
+
+// Tests that aliases can be sorted by their usage in the run history.
+pier_test!(cli => test_list_aliases_sorted_by_usage, cfg => CONFIG_1,
+| cfg: ChildPath, mut cmd: Command | {
+    for alias in &["test_cmd_1", "test_cmd_2", "test_cmd_2"] {
+        crate::common::cli_cmd(&cfg)
+            .args(&["run", alias])
+            .assert()
+            .success();
+    }
+
+    cmd.args(&["list", "-q", "--sort", "count"]);
+    cmd.assert()
+        .success()
+        .stdout(contains(trim!(r#"
+            test_cmd_2
+            test_cmd_1
+            inline-with-args
+        "#)));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["stats"])
+        .assert()
+        .success()
+        .stdout(contains("Never used scripts:\ninline-with-args\nshebang-with-args"));
+});
+
+// Tests that aliases are listed by frecency unless sorted by name.
+pier_test!(cli => test_list_aliases_sorted_by_frecency, cfg => CONFIG_1,
+| cfg: ChildPath, mut cmd: Command | {
+    for alias in &["test_cmd_1", "test_cmd_2", "test_cmd_2"] {
+        crate::common::cli_cmd(&cfg)
+            .args(&["run", alias])
+            .assert()
+            .success();
+    }
+
+    cmd.args(&["list", "-q"]);
+    cmd.assert()
+        .success()
+        .stdout(starts_with("test_cmd_2\ntest_cmd_1\ninline-with-args\nshebang-with-args\n"));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["list", "-q", "--sort", "name"])
+        .assert()
+        .success()
+        .stdout(starts_with("inline-with-args\nshebang-with-args\ntest_cmd_1\ntest_cmd_2\n"));
+});
+
+// Tests that aliases are listed by name when the run history can't be read.
+pier_test!(cli => test_list_aliases_with_broken_history, cfg => CONFIG_1,
+| cfg: ChildPath, mut cmd: Command | {
+    let state = cfg.path().parent().unwrap().join("state/pier");
+    std::fs::create_dir_all(&state).unwrap();
+    std::fs::write(state.join("history.toml"), "not = [valid").unwrap();
+
+    let by_name = crate::common::cli_cmd(&cfg)
+        .args(["list", "-q", "--sort", "name"])
+        .output()
+        .unwrap()
+        .stdout;
+
+    cmd.args(["list", "-q"]);
+    cmd.assert()
+        .success()
+        .stdout(String::from_utf8(by_name).unwrap())
+        .stderr(contains("history.toml"));
+});
+
+// Tests that logged runs capture stdout and stderr in a log file.
+pier_test!(cli => test_run_with_log, cfg => r#"
+[scripts.noisy]
+command = '''
+#!/bin/sh
+echo "to-stdout $1"
+echo "to-stderr" >&2
+exit 6
+'''
+
+[scripts.always_logged]
+command = 'echo logged-by-config'
+log = true
+
+[scripts.binary]
+command = 'printf "caf\\351\\nprompt: "'
+
+[scripts.guarded]
+command = 'echo guarded'
+log = true
+
+[scripts.guarded.hooks]
+before = 'exit 5'
+
+[scripts.broken]
+command = '''#!/nonexistent/interpreter
+echo unreachable'''
+log = true
+"#, | cfg: ChildPath, mut cmd: Command | {
+    cmd.args(["run", "--log", "noisy", "arg"]);
+    cmd.assert()
+        .failure()
+        .code(6)
+        .stdout(contains("to-stdout arg"))
+        .stderr(contains("to-stderr"));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["run", "always_logged"])
+        .env("SHELL", "/bin/sh")
+        .assert()
+        .success();
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["logs", "noisy", "--follow"])
+        .assert()
+        .success()
+        .stdout(contains("[stdout] to-stdout arg"))
+        .stdout(contains("[stderr] to-stderr"))
+        .stdout(contains("[pier] Script exited with 6"));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["logs", "always_logged", "--run", "2"])
+        .assert()
+        .success()
+        .stdout(contains("[stdout] logged-by-config"));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["logs", "always_logged", "--run", "1"])
+        .assert()
+        .failure();
+
+    // Output that isn't UTF-8 or doesn't end with a newline is copied as it is.
+    crate::common::cli_cmd(&cfg)
+        .args(&["run", "--log", "binary"])
+        .env("SHELL", "/bin/sh")
+        .assert()
+        .success()
+        .stdout(&b"caf\xe9\nprompt: "[..]);
+    crate::common::cli_cmd(&cfg)
+        .args(&["logs", "binary"])
+        .assert()
+        .success()
+        .stdout(predicate::function(|log: &[u8]| {
+            log.windows(13).any(|window| window == b"[stdout] caf\xe9")
+                && log.windows(17).any(|window| window == b"[stdout] prompt: ")
+        }));
+
+    // Logs of runs that were stopped by a hook or couldn't start have an end to follow to.
+    for alias in &["guarded", "broken"] {
+        crate::common::cli_cmd(&cfg)
+            .args(["run", alias])
+            .env("SHELL", "/bin/sh")
+            .assert()
+            .failure();
+        let mut follow = crate::common::cli_cmd(&cfg)
+            .args(["logs", alias, "--follow"])
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .expect("Failed to follow the log");
+        let mut exited = false;
+        for _ in 0..50 {
+            if follow.try_wait().expect("Failed to wait for logs").is_some() {
+                exited = true;
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        if !exited {
+            follow.kill().expect("Failed to stop following the log");
+        }
+        assert!(exited, "Following the log of {} never ended", alias);
+    }
+    crate::common::cli_cmd(&cfg)
+        .args(["logs", "guarded"])
+        .assert()
+        .success()
+        .stdout(contains("[pier] Script exited with 5"));
+    crate::common::cli_cmd(&cfg)
+        .args(["logs", "broken"])
+        .assert()
+        .success()
+        .stdout(contains("[pier] Script exited with error"));
+});
+
+// Tests that detached scripts run in the background and can be attached to.
+pier_test!(cli => test_run_detached, cfg => r#"
+[scripts.background]
+command = '''
+#!/bin/sh
+sleep 0.2
+echo "detached-output $1"
+'''
+"#, | cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["run", "--detach", "background", "arg"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("Started job 1 for background"));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["attach", "1"])
+        .assert()
+        .success()
+        .stdout(contains("[stdout] detached-output arg"))
+        .stdout(contains("[pier] Script exited with 0"));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["kill", "1"])
+        .assert()
+        .failure()
+        .stderr(contains("JobNotRunning"));
+});
+
+// Tests that jobs created at the same time all get their own number.
+pier_test!(basic => test_create_jobs_concurrently, | te: crate::common::TestEnv | {
+    let dir = te.dir.path().join("jobs");
+    let start = std::sync::Arc::new(std::sync::Barrier::new(8));
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let jobs = Jobs::new(dir.clone());
+            let start = start.clone();
+            std::thread::spawn(move || {
+                let job = Job {
+                    id: 0,
+                    alias: String::from("background"),
+                    args: vec![],
+                    config: PathBuf::from("pier.toml"),
+                    cwd: PathBuf::from("/"),
+                    parallel: false,
+                    started: chrono::Local::now(),
+                    pid: None,
+                    log: None,
+                    finished: None,
+                    exit_code: None,
+                    signal: None,
+                    error: None,
+                };
+                start.wait();
+                jobs.create(job).expect("Failed to create job").id
+            })
+        })
+        .collect();
+    let mut ids: Vec<usize> = handles
+        .into_iter()
+        .map(|handle| handle.join().expect("Job thread panicked"))
+        .collect();
+    ids.sort_unstable();
+
+    assert_eq!(ids, (1..=8).collect::<Vec<usize>>());
+    assert_eq!(Jobs::new(dir).list().expect("Failed to list jobs").len(), 8);
+});
+
+// Tests that killing a job signals the processes of the script.
+pier_test!(cli => test_kill_detached, cfg => r#"
+[scripts.forever]
+command = '''
+#!/bin/sh
+echo started-forever
+sleep 30
+'''
+"#, | cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["run", "--detach", "forever"]);
+    cmd.assert().success();
+
+    let mut running = false;
+    for _ in 0..50 {
+        let output = crate::common::cli_cmd(&cfg)
+            .arg("jobs")
+            .output()
+            .expect("Failed to list jobs");
+        if String::from_utf8_lossy(&output.stdout).contains("running") {
+            running = true;
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert!(running, "Job never started running");
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["kill", "1", "--signal", "KILL"])
+        .assert()
+        .success();
+
+    crate::common::cli_cmd(&cfg)
+        .arg("jobs")
+        .assert()
+        .success()
+        .stdout(contains("killed (SIGKILL)"));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["attach", "1"])
+        .assert()
+        .success()
+        .stdout(contains("[pier] Script exited with signal 9"));
+});
+
+// Tests that a signal the script handles doesn't end the job, while a later signal does.
+pier_test!(cli => test_kill_detached_with_handled_signal, cfg => r#"
+[scripts.reloading]
+command = '''
+#!/bin/sh
+trap 'echo reloaded' HUP
+echo started-reloading
+while [ ! -e stop ]; do sleep 0.1; done
+echo still-running
+sleep 30
+'''
+"#, | cfg: ChildPath, mut cmd: Command | {
+    let jobs_output = || {
+        let output = crate::common::cli_cmd(&cfg)
+            .arg("jobs")
+            .output()
+            .expect("Failed to list jobs");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    let wait_for = |expected: &str| {
+        let found = (0..50).any(|_| {
+            let output = crate::common::cli_cmd(&cfg)
+                .args(["logs", "reloading"])
+                .output()
+                .expect("Failed to print the log");
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            String::from_utf8_lossy(&output.stdout).contains(expected)
+        });
+        assert!(found, "The log never showed {}", expected);
+    };
+
+    cmd.args(["run", "--detach", "reloading"]);
+    cmd.assert().success();
+    wait_for("started-reloading");
+
+    crate::common::cli_cmd(&cfg)
+        .args(["kill", "1", "--signal", "HUP"])
+        .assert()
+        .success();
+    wait_for("reloaded");
+    std::fs::write(cfg.path().parent().unwrap().join("stop"), "").unwrap();
+    wait_for("still-running");
+    assert!(jobs_output().contains("running"));
+
+    crate::common::cli_cmd(&cfg)
+        .args(["kill", "1"])
+        .assert()
+        .success();
+    crate::common::cli_cmd(&cfg)
+        .args(["attach", "1"])
+        .assert()
+        .success()
+        .stdout(contains("[pier] Script exited with"));
+    // The supervisor records the end of the job right after the end of the log.
+    let killed = (0..50).any(|_| {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        jobs_output().contains("killed (SIGTERM)")
+    });
+    assert!(killed, "The job was never recorded as killed: {}", jobs_output());
+});
+
+// Tests that watched scripts run again when a matching file changes.
+pier_test!(cli => test_watch, cfg => r#"
+[scripts.watched]
+command = '''
+#!/bin/sh
+echo "watched-run $1"
+'''
+"#, | cfg: ChildPath, _cmd: Command | {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    let dir = cfg.path().parent().expect("Config has a parent directory");
+    let mut child = crate::common::cli_cmd(&cfg)
+        .args(&["watch", "watched", "--glob", "*.txt", "--debounce", "50", "--", "arg"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start pier watch");
+
+    let (sender, receiver) = channel();
+    let stdout = child.stdout.take().expect("Watch stdout is piped");
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let first = receiver.recv_timeout(Duration::from_secs(10));
+    // Changes to files which don't match the glob don't trigger a run.
+    std::fs::write(dir.join("ignored.md"), "ignored").expect("Failed to write file");
+    std::thread::sleep(Duration::from_millis(500));
+    std::fs::write(dir.join("changed.txt"), "changed").expect("Failed to write file");
+    let second = receiver.recv_timeout(Duration::from_secs(10));
+
+    child.kill().expect("Failed to stop pier watch");
+    child.wait().expect("Failed to wait for pier watch");
+
+    assert_eq!(first.as_deref(), Ok("watched-run arg"));
+    assert_eq!(second.as_deref(), Ok("watched-run arg"));
+    assert!(receiver.try_recv().is_err(), "Watched script ran too often");
+});
+
+// Tests that the daemon catches up on missed runs according to the policy of each script.
+pier_test!(cli => test_daemon_catch_up, cfg => r#"
+[scripts.every_missed]
+command = "echo every-missed"
+schedule = "0 3 * * *"
+catch_up = "all"
+
+[scripts.once_missed]
+command = "echo once-missed"
+schedule = "0 3 * * *"
+catch_up = "once"
+
+[scripts.skip_missed]
+command = "echo skip-missed"
+schedule = "0 3 * * *"
+
+[scripts.new_schedule]
+command = "echo new-schedule"
+schedule = "* * * * *"
+"#, | cfg: ChildPath, mut cmd: Command | {
+    let state_dir = cfg.path().parent().expect("Config has a parent directory").join("state/pier");
+    let last_fire = (chrono::Local::now() - chrono::Duration::days(3)).to_rfc3339();
+    std::fs::create_dir_all(&state_dir).expect("Failed to create state directory");
+    std::fs::write(
+        state_dir.join("schedule.toml"),
+        format!(
+            "[last_fire]\nevery_missed = \"{0}\"\nonce_missed = \"{0}\"\nskip_missed = \"{0}\"\n",
+            last_fire
+        ),
+    )
+    .expect("Failed to write schedule state");
+
+    cmd.args(&["daemon", "--once"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("Skipped missed runs of skip_missed"));
+
+    let output = crate::common::cli_cmd(&cfg)
+        .arg("runs")
+        .output()
+        .expect("Failed to list runs");
+    let runs = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(runs.matches("every_missed").count(), 3);
+    assert_eq!(runs.matches("once_missed").count(), 1);
+    assert!(!runs.contains("skip_missed"));
+    assert!(!runs.contains("new_schedule"));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["schedule", "ls"])
+        .assert()
+        .success()
+        .stdout(contains("new_schedule"))
+        .stdout(contains("03:00:00"));
+});
+
+// Tests that the HTTP API lists, shows and runs allowed scripts for authorized clients.
+pier_test!(cli => test_serve, cfg => r#"
+[scripts.greet]
+command = 'echo "hello $1"; exit 3'
+description = 'Greets someone'
+
+[scripts.private]
+command = 'echo private'
+"#, | cfg: ChildPath, _cmd: Command | {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpStream;
+    use std::process::Stdio;
+
+    let mut child = crate::common::cli_cmd(&cfg)
+        .args(&["serve", "--listen", "127.0.0.1:0", "--token", "secret", "--allow", "greet"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start pier serve");
+
+    let mut listening = String::new();
+    BufReader::new(child.stdout.as_mut().expect("Serve stdout is piped"))
+        .read_line(&mut listening)
+        .expect("Failed to read address");
+    let address = listening.trim().trim_start_matches("Listening on http://").to_string();
+    let request = |request: &str| {
+        crate::common::http_request(TcpStream::connect(&address).expect("Failed to connect"), request)
+    };
+
+    let unauthorized = request("GET /scripts HTTP/1.0\r\n\r\n");
+    let list = request("GET /scripts HTTP/1.0\r\nAuthorization: Bearer secret\r\n\r\n");
+    let show = request("GET /scripts/greet HTTP/1.0\r\nAuthorization: Bearer secret\r\n\r\n");
+    let run = request(
+        "POST /scripts/greet/run HTTP/1.0\r\nAuthorization: Bearer secret\r\nContent-Length: 17\r\n\r\n{\"args\":[\"pier\"]}",
+    );
+    let forbidden = request("POST /scripts/private/run HTTP/1.0\r\nAuthorization: Bearer secret\r\n\r\n");
+    let missing = request("GET /scripts/missing HTTP/1.0\r\nAuthorization: Bearer secret\r\n\r\n");
+
+    child.kill().expect("Failed to stop pier serve");
+    child.wait().expect("Failed to wait for pier serve");
+
+    assert!(unauthorized.starts_with("HTTP/1.0 401"), "{}", unauthorized);
+    assert!(list.contains(r#"{"alias":"greet","description":"Greets someone","runnable":true"#), "{}", list);
+    assert!(list.contains(r#"{"alias":"private","description":null,"runnable":false"#), "{}", list);
+    assert!(show.contains(r#""command":"echo \"hello $1\"; exit 3""#), "{}", show);
+    assert!(run.contains(r#"{"line":"hello pier","stream":"stdout"}"#), "{}", run);
+    assert!(run.contains(r#"{"exit_code":3}"#), "{}", run);
+    assert!(forbidden.starts_with("HTTP/1.0 403"), "{}", forbidden);
+    assert!(missing.starts_with("HTTP/1.0 404"), "{}", missing);
+});
+
+// Tests that the HTTP API can listen on a unix socket without a token.
+pier_test!(cli => test_serve_socket, cfg => r#"
+[scripts.greet]
+command = 'echo hello'
+"#, | cfg: ChildPath, _cmd: Command | {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+    use std::process::Stdio;
+
+    let socket = cfg.path().parent().expect("Config has a parent directory").join("pier.sock");
+    let mut child = crate::common::cli_cmd(&cfg)
+        .args(&["serve", "--socket", socket.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start pier serve");
+
+    let mut listening = String::new();
+    BufReader::new(child.stdout.as_mut().expect("Serve stdout is piped"))
+        .read_line(&mut listening)
+        .expect("Failed to read socket");
+
+    let list = crate::common::http_request(
+        UnixStream::connect(&socket).expect("Failed to connect"),
+        "GET /scripts HTTP/1.0\r\n\r\n",
+    );
+
+    child.kill().expect("Failed to stop pier serve");
+    child.wait().expect("Failed to wait for pier serve");
+
+    assert!(list.starts_with("HTTP/1.0 200"), "{}", list);
+    assert!(list.contains(r#""alias":"greet""#), "{}", list);
+});
+
+// Tests that serving on an address requires a token.
+pier_test!(cli => test_serve_requires_token, cfg => r#"
+[scripts.greet]
+command = 'echo hello'
+"#, | _cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["serve", "--listen", "127.0.0.1:0"]);
+    cmd.env_remove("PIER_SERVE_TOKEN");
+    cmd.assert()
+        .failure()
+        .stderr(contains("ServeTokenMissing"));
+});
+
+// Tests that the JSON-RPC mode answers requests and streams the output of runs.
+pier_test!(cli => test_rpc, cfg => r#"
+[scripts.greet]
+command = 'echo "hello $1"'
+description = 'Greets someone'
+tags = ['greeting']
+"#, | cfg: ChildPath, mut cmd: Command | {
+    let requests = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"list","params":{"tags":["greeting"]}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"add","params":{"alias":"tools/farewell","command":"echo bye"}}"#,
+        r#"{"jsonrpc":"2.0","id":3,"method":"edit","params":{"alias":"farewell","description":"Says bye"}}"#,
+        r#"{"jsonrpc":"2.0","id":4,"method":"search","params":{"query":"BYE"}}"#,
+        r#"{"jsonrpc":"2.0","id":5,"method":"show","params":{"alias":"missing"}}"#,
+        r#"{"jsonrpc":"2.0","id":6,"method":"unknown"}"#,
+        r#"{"jsonrpc":"2.0","id":7,"method":"run","params":{"alias":"greet","args":["pier"]}}"#,
+        r#"{"jsonrpc":"2.0","id":8,"method":"add","params":{"alias":"greet","command":"echo hi"}}"#,
+    ];
+    let mut child = cmd
+        .arg("rpc")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to start pier rpc");
+    {
+        use std::io::Write;
+        // Closing stdin ends the session once every request was answered.
+        let mut stdin = child.stdin.take().expect("Rpc stdin is piped");
+        stdin
+            .write_all(requests.join("\n").as_bytes())
+            .expect("Failed to send requests");
+    }
+
+    let output = child.wait_with_output().expect("Failed to run pier rpc");
+    assert!(output.status.success());
+
+    let messages: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Every line is JSON"))
+        .collect();
+    let response = |id: u64| {
+        messages
+            .iter()
+            .find(|message| message["id"] == id)
+            .unwrap_or_else(|| panic!("No response to request {}", id))
+    };
+
+    assert_eq!(response(1)["result"][0]["alias"], "greet");
+    assert_eq!(response(2)["result"]["command"], "echo bye");
+    assert_eq!(response(3)["result"]["description"], "Says bye");
+    assert_eq!(response(3)["result"]["alias"], "tools/farewell");
+    assert_eq!(response(4)["result"].as_array().map(Vec::len), Some(1));
+    assert_eq!(response(5)["error"]["data"]["kind"], "AliasNotFound");
+    assert_eq!(response(6)["error"]["code"], -32601);
+    assert_eq!(response(7)["result"]["exit_code"], 0);
+    assert_eq!(response(8)["error"]["data"]["kind"], "AliasAlreadyExists");
+    assert!(messages.iter().any(|message| {
+        message["method"] == "run/output"
+            && message["params"]["id"] == 7
+            && message["params"]["line"] == "hello pier"
+    }));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["show", "farewell"])
+        .assert()
+        .success()
+        .stdout(contains("echo bye"));
+});
+
+// Tests that scripts are exported as shell functions, inlined or calling pier.
+pier_test!(cli => test_export_shell, cfg => r#"
+[scripts.greet]
+command = 'echo "hello $1"'
+description = 'Greets someone'
+tags = ['greeting']
+
+[scripts.build]
+command = 'cargo build'
+
+[scripts.leave]
+command = 'cd / && exit 3'
+"#, | cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["export", "shell", "--format", "bash", "--tag", "greeting"])
+        .assert()
+        .success()
+        .stdout(contains("# Greets someone\ngreet() {\n"))
+        .stdout(contains("run greet -- \"$@\"\n}"))
+        .stdout(contains("build").not());
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["export", "shell", "--format", "bash", "--inline"])
+        .assert()
+        .success()
+        .stdout(contains("greet() (\n    echo \"hello $1\"\n)"))
+        .stdout(contains("build() (\n    cargo build\n)"));
+
+    // Inlined commands can't change the directory of the shell or end it.
+    let functions = cfg.path().parent().unwrap().join("functions.sh");
+    let export = crate::common::cli_cmd(&cfg)
+        .args(&["export", "shell", "--format", "bash", "--inline"])
+        .output()
+        .expect("Failed to export the functions");
+    std::fs::write(&functions, export.stdout).expect("Failed to write the functions");
+    let dir = cfg.path().parent().unwrap();
+    Command::new("bash")
+        .current_dir(dir)
+        .arg("-c")
+        .arg(format!(". {}; leave; echo \"left with $?\"; pwd", functions.display()))
+        .assert()
+        .success()
+        .stdout(format!("left with 3\n{}\n", dir.display()));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["export", "shell", "--format", "fish"])
+        .assert()
+        .success()
+        .stdout(contains("function greet\n"))
+        .stdout(contains("run greet -- $argv\nend"));
+});
+
+// Tests that installed shims run their script, follow a move and are removed on uninstall.
+pier_test!(cli => test_install_shims, cfg => r#"
+[scripts.greet]
+command = 'echo "hello $1"'
+
+[scripts.ls]
+command = 'ls'
+"#, | cfg: ChildPath, mut cmd: Command | {
+    let bin_dir = cfg.path().parent().unwrap().join("bin");
+    let bin = bin_dir.to_str().unwrap();
+    cmd.args(&["install", "greet", "--bin-dir", bin])
+        .assert()
+        .success()
+        .stdout(contains("Installed greet"));
+
+    let shim = bin_dir.join("greet");
+    Command::new(&shim)
+        .arg("pier")
+        .assert()
+        .success()
+        .stdout("hello pier\n");
+
+    // A shim named ls would shadow the ls on $PATH.
+    crate::common::cli_cmd(&cfg)
+        .args(&["install", "ls", "--bin-dir", bin])
+        .assert()
+        .failure()
+        .stderr(contains("ShimShadowsBinary"));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["mv", "greet", "hello"])
+        .assert()
+        .success();
+    assert!(!shim.exists());
+    Command::new(bin_dir.join("hello"))
+        .arg("again")
+        .assert()
+        .success()
+        .stdout("hello again\n");
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["uninstall", "--all"])
+        .assert()
+        .success()
+        .stdout(contains("Removed"));
+    assert!(!bin_dir.join("hello").exists());
+});
+
+// Tests that scripts are built into standalone executables which don't need pier.
+pier_test!(cli => test_build_script, cfg => r#"
+[scripts.greet]
+command = 'echo "hello $1"'
+
+[scripts.deploy]
+[[scripts.deploy.steps]]
+command = 'echo "build $TARGET"; false'
+continue_on_error = true
+env = { TARGET = 'release' }
+
+[[scripts.deploy.steps]]
+command = '''#!/bin/sh
+echo "ship $1"'''
+
+[[scripts.deploy.steps]]
+command = 'exit 3'
+
+[[scripts.deploy.steps]]
+command = 'echo never'
+
+[scripts.release]
+command = 'echo release'
+depends_on = ['deploy']
+"#, | cfg: ChildPath, mut cmd: Command | {
+    let dir = cfg.path().parent().unwrap();
+    cmd.args(&["build", "greet"])
+        .assert()
+        .success()
+        .stdout(contains("Created greet"));
+    Command::new(dir.join("greet"))
+        .arg("pier")
+        .assert()
+        .success()
+        .stdout("hello pier\n");
+
+    let output = dir.join("deploy.sh");
+    crate::common::cli_cmd(&cfg)
+        .args(&["build", "deploy", "-o", output.to_str().unwrap()])
+        .assert()
+        .success();
+    Command::new(&output)
+        .arg("it")
+        .assert()
+        .code(3)
+        .stdout("build release\nship it\n");
+
+    crate::common::cli_cmd(&cfg)
+        .args(&["build", "greet"])
+        .assert()
+        .failure()
+        .stderr(contains("BuildOutputExists"));
+    crate::common::cli_cmd(&cfg)
+        .args(&["build", "release"])
+        .assert()
+        .failure()
+        .stderr(contains("BuildDependencies"));
+});
+
+// Tests that the catalog groups the scripts by tag in markdown and html.
+pier_test!(cli => test_docs, cfg => r#"
+[scripts.greet]
+command = 'echo "hello <$1>"'
+description = 'Greets someone'
+tags = ['greeting', 'demo']
+
+[scripts.stats]
+command = '''#!/usr/bin/env python3
+print("stats")'''
+reference = '~/bin/stats.py'
+tags = ['demo']
+
+[scripts.other]
+command = 'echo other'
+"#, | cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["docs"])
+        .assert()
+        .success()
+        .stdout(contains("## demo\n\n### greet\n\nGreets someone\n\n```sh\necho \"hello <$1>\"\n```"))
+        .stdout(contains("### stats\n\nReference: `~/bin/stats.py`\n\n```python\n#!/usr/bin/env python3"))
+        .stdout(contains("## greeting\n\n### greet"))
+        .stdout(contains("## Untagged\n\n### other"));
+
+    let output = cfg.path().parent().unwrap().join("catalog.html");
+    crate::common::cli_cmd(&cfg)
+        .args(&["docs", "--tag", "greeting", "-o", output.to_str().unwrap()])
+        .assert()
+        .success();
+    let html = std::fs::read_to_string(&output).expect("Docs are written");
+    assert!(html.contains("<h3>greet</h3>"));
+    assert!(html.contains("<code class=\"language-sh\">echo &quot;hello &lt;$1&gt;&quot;</code>"));
+    assert!(!html.contains("stats"));
+
+    // Without any tags the scripts are right under the title.
+    cfg.write_str("[scripts.other]\ncommand = 'echo other'\n").expect("Failed to write config");
+    crate::common::cli_cmd(&cfg)
+        .args(&["docs"])
+        .assert()
+        .success()
+        .stdout(starts_with("# Scripts\n\n## other\n"));
+});
+
+// Tests that scripts named like the tables of a script, such as watch or hooks, stay in their
+// group when the config is read and written again.
+pier_test!(cli => test_groups_with_script_table_names, cfg => r#"
+[scripts.k8s.watch]
+command = 'echo watching'
+
+[scripts.k8s.forward-mongo]
+command = 'echo forward'
+
+[scripts.ci.hooks]
+command = 'echo hooks'
+
+[scripts.deploy]
+command = 'echo deploy'
+
+[scripts.deploy.hooks]
+after = 'echo deployed'
+"#, | cfg: ChildPath, mut cmd: Command | {
+    cmd.args(["ls", "-q", "--sort", "name"])
+        .assert()
+        .success()
+        .stdout("ci/hooks\ndeploy\nk8s/forward-mongo\nk8s/watch\n");
+
+    crate::common::cli_cmd(&cfg)
+        .args(["add", "-a", "other", "echo other"])
+        .assert()
+        .success();
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "k8s/watch"])
+        .assert()
+        .success()
+        .stdout("watching\n");
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "ci/hooks"])
+        .assert()
+        .success()
+        .stdout("hooks\n");
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "deploy"])
+        .assert()
+        .success()
+        .stdout("deploy\ndeployed\n");
+});
+
+// Tests that grouped aliases can be listed as a tree and reached by their last part.
+pier_test!(cli => test_namespaced_aliases, cfg => r#"
+[scripts.hello]
+command = 'echo hello'
+
+[scripts.docker.flush]
+command = 'echo flush'
+description = 'Flushes the containers'
+
+[scripts.docker.clean]
+command = 'echo docker clean'
+
+[scripts.docker.stats]
+command = '''#!/bin/sh
+echo "stats $1"'''
+
+[scripts."k8s.forward-mongo"]
+command = 'echo forward'
+
+[scripts.team.db.clean]
+command = 'echo db clean'
+"#, | cfg: ChildPath, mut cmd: Command | {
+    cmd.args(&["ls", "--tree"])
+        .assert()
+        .success()
+        .stdout(concat!(
+            "docker/\n",
+            "├── clean\n",
+            "├── flush  Flushes the containers\n",
+            "└── stats\n",
+            "hello\n",
+            "k8s.\n",
+            "└── forward-mongo\n",
+            "team/\n",
+            "└── db/\n",
+            "    └── clean\n",
+        ));
+    crate::common::cli_cmd(&cfg)
+        .args(["ls", "-q", "docker/"])
+        .assert()
+        .success()
+        .stdout("docker/clean\ndocker/flush\ndocker/stats\n");
+    crate::common::cli_cmd(&cfg)
+        .args(["ls", "-q", "team"])
+        .assert()
+        .success()
+        .stdout("team/db/clean\n");
+    // Both group separators are the same when looking up scripts and groups.
+    crate::common::cli_cmd(&cfg)
+        .args(["ls", "-q", "k8s/"])
+        .assert()
+        .success()
+        .stdout("k8s.forward-mongo\n");
+    crate::common::cli_cmd(&cfg)
+        .args(["ls", "-q", "docker."])
+        .assert()
+        .success()
+        .stdout("docker/clean\ndocker/flush\ndocker/stats\n");
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "docker.flush"])
+        .assert()
+        .success()
+        .stdout("flush\n");
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "k8s/forward-mongo"])
+        .assert()
+        .success()
+        .stdout("forward\n");
+
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "flush"])
+        .assert()
+        .success()
+        .stdout("flush\n");
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "--log", "flush"])
+        .assert()
+        .success();
+    crate::common::cli_cmd(&cfg)
+        .args(["logs", "flush"])
+        .assert()
+        .success()
+        .stdout(contains("[stdout] flush"));
+    crate::common::cli_cmd(&cfg)
+        .args(["runs", "flush"])
+        .assert()
+        .success()
+        .stdout(contains("docker/flush"));
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "docker/stats", "now"])
+        .assert()
+        .success()
+        .stdout("stats now\n");
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "forward-mongo"])
+        .assert()
+        .success()
+        .stdout("forward\n");
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "db/clean"])
+        .assert()
+        .success()
+        .stdout("db clean\n");
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "clean"])
+        .assert()
+        .failure()
+        .stderr(contains("could be any of docker/clean, team/db/clean"));
+
+    let socket = cfg.path().parent().expect("Config has a parent directory").join("pier.sock");
+    let mut child = crate::common::cli_cmd(&cfg)
+        .args(["serve", "--socket", socket.to_str().unwrap(), "--allow", "flush"])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to start pier serve");
+    let mut listening = String::new();
+    std::io::BufRead::read_line(
+        &mut std::io::BufReader::new(child.stdout.as_mut().expect("Serve stdout is piped")),
+        &mut listening,
+    )
+    .expect("Failed to read socket");
+    let request = |request: &str| {
+        crate::common::http_request(
+            std::os::unix::net::UnixStream::connect(&socket).expect("Failed to connect"),
+            request,
+        )
+    };
+    let show = request("GET /scripts/docker%2Fflush HTTP/1.0\r\n\r\n");
+    let run = request("POST /scripts/docker/flush/run HTTP/1.0\r\n\r\n");
+    let forbidden = request("POST /scripts/docker/clean/run HTTP/1.0\r\n\r\n");
+    child.kill().expect("Failed to stop pier serve");
+    child.wait().expect("Failed to wait for pier serve");
+    assert!(show.contains(r#""alias":"docker/flush""#), "{}", show);
+    assert!(show.contains(r#""runnable":true"#), "{}", show);
+    assert!(run.contains(r#"{"line":"flush","stream":"stdout"}"#), "{}", run);
+    assert!(forbidden.starts_with("HTTP/1.0 403"), "{}", forbidden);
+
+    let bin = cfg.path().parent().expect("Config has a parent directory").join("bin");
+    crate::common::cli_cmd(&cfg)
+        .args(["install", "flush", "--bin-dir", bin.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(contains("Installed docker/flush as"));
+    Command::new(bin.join("flush")).assert().success().stdout("flush\n");
+    crate::common::cli_cmd(&cfg)
+        .args(["mv", "flush", "docker/purge"])
+        .assert()
+        .success();
+    assert!(!bin.join("flush").exists());
+    Command::new(bin.join("purge")).assert().success().stdout("flush\n");
+
+    // Scripts of different groups with the same name can't share a shim.
+    crate::common::cli_cmd(&cfg)
+        .args(["install", "--all", "--bin-dir", bin.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(contains("ShimNameTaken"));
+    assert!(!bin.join("clean").exists());
+    crate::common::cli_cmd(&cfg)
+        .args(["install", "docker/clean", "--bin-dir", bin.to_str().unwrap()])
+        .assert()
+        .success();
+    crate::common::cli_cmd(&cfg)
+        .args(["install", "team/db/clean", "--bin-dir", bin.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(contains("ShimNameTaken"));
+    Command::new(bin.join("clean")).assert().success().stdout("docker clean\n");
+
+    crate::common::cli_cmd(&cfg)
+        .args(["mv", "hello", "greet/hello"])
+        .assert()
+        .success();
+    let config = std::fs::read_to_string(cfg.path()).expect("Config is written");
+    assert!(config.contains("[scripts.greet.hello]"));
+    assert!(config.contains("[scripts.team.db.clean]"));
+    assert!(config.contains("[scripts.\"k8s.forward-mongo\"]"));
+});
+
+// Tests that bundles are exported by tag and imported with their referenced files.
+pier_test!(cli => test_bundle, cfg => r#"
+[scripts.greet]
+command = 'echo mine'
+"#, | cfg: ChildPath, mut cmd: Command | {
+    let root = cfg.path().parent().unwrap();
+    crate::common::write_executable(&root.join("bin/backup.sh"), "#!/bin/sh\necho \"backup $1\"\n");
+    let source = root.join("source.toml");
+    std::fs::write(&source, format!(r#"
+[scripts.greet]
+command = 'echo "hello $1"'
+tags = ['shared']
+
+[scripts.backup]
+reference = '{}'
+tags = ['shared']
+
+[scripts.private]
+command = 'echo private'
+"#, root.join("bin/backup.sh").display())).expect("Unable to write the source config");
+
+    let bundle = root.join("shared.pier.toml");
+    Command::cargo_bin("pier")
+        .expect("Failed to set cargo binary pier")
+        .args(&["-c", source.to_str().unwrap(), "export", "bundle", "--tag", "shared"])
+        .args(&["-o", bundle.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let files_dir = root.join("files");
+    let import = [
+        "import",
+        "bundle",
+        bundle.to_str().unwrap(),
+        "--files-dir",
+        files_dir.to_str().unwrap(),
+    ];
+    cmd.args(&import)
+        .args(&["--conflict", "rename", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(contains("Would add backup\n  and write"))
+        .stdout(contains("Would add greet as greet-2"))
+        .stdout(contains("private").not());
+    assert!(!files_dir.exists());
+
+    crate::common::cli_cmd(&cfg)
+        .args(&import)
+        .args(&["--conflict", "rename"])
+        .assert()
+        .success()
+        .stdout(contains("Imported 2 scripts"));
+    crate::common::cli_cmd(&cfg)
+        .args(&["run", "backup", "now"])
+        .assert()
+        .success()
+        .stdout(contains("backup now"));
+    crate::common::cli_cmd(&cfg)
+        .args(&["run", "greet-2", "pier"])
+        .assert()
+        .success()
+        .stdout(contains("hello pier"));
+
+    crate::common::cli_cmd(&cfg)
+        .args(&import)
+        .args(&["--conflict", "skip"])
+        .assert()
+        .success()
+        .stdout(contains("Skipped greet, the alias already exists"))
+        .stdout(contains("Imported 0 scripts"));
+});
+
+// Tests that a bundle can't write files outside of the files directory
+pier_test!(cli => test_bundle_unsafe_paths, cfg => r#""#, | cfg: ChildPath, mut cmd: Command | {
+    let root = cfg.path().parent().unwrap();
+    let files_dir = root.join("files");
+    let bundle = root.join("evil.pier.toml");
+    let paths = [("evil", "../../.bashrc"), ("evil", "/tmp/evil.sh"), ("../up", "evil.sh")];
+    for (alias, name) in &paths {
+        std::fs::write(&bundle, format!(r#"
+[scripts."{0}"]
+reference = '/tmp/{1}'
+
+[files."{0}"]
+name = '{1}'
+content = 'echo pwned'
+"#, alias, name)).expect("Unable to write the bundle");
+
+        crate::common::cli_cmd(&cfg)
+            .args(&["import", "bundle", bundle.to_str().unwrap()])
+            .args(&["--files-dir", files_dir.to_str().unwrap()])
+            .assert()
+            .failure()
+            .stderr(contains("UnsafeBundlePath"));
+    }
+    assert!(!files_dir.exists());
+    assert!(!root.join(".bashrc").exists());
+
+    cmd.args(&["list"]).assert().failure().stderr(contains("No scripts exist"));
+});
+
+// Tests that scripts of git repositories are read-only and follow the ref of their source.
+pier_test!(cli => test_git_sources, cfg => r#""#, | cfg: ChildPath, mut cmd: Command | {
+    let root = cfg.path().parent().unwrap();
+    let remote = root.join("remote.git");
+    let work = root.join("work");
+    std::fs::create_dir_all(&remote).expect("Failed to create the repository");
+    crate::common::git(&remote, &["init", "--quiet", "--bare"]);
+    crate::common::git(root, &["clone", "--quiet", remote.to_str().unwrap(), "work"]);
+    std::fs::write(work.join("pier.toml"), concat!(
+        "[scripts.build]\ncommand = 'echo source build'\n",
+        "[scripts.deploy]\ncommand = 'echo deploy v1'\ndepends_on = ['build']\n",
+    )).expect("Failed to write the source config");
+    crate::common::git(&work, &["add", "pier.toml"]);
+    crate::common::git(&work, &["commit", "--quiet", "-m", "Add deploy"]);
+    crate::common::git(&work, &["tag", "v1"]);
+    crate::common::git(&work, &["push", "--quiet", "--tags", "origin", "HEAD"]);
+
+    cfg.write_str(&format!(r#"
+[scripts.local]
+command = 'echo local'
+
+[scripts.build]
+command = 'echo LOCAL-build'
+
+[[sources]]
+git = '{0}'
+prefix = 'team/'
+
+[[sources]]
+git = '{0}'
+ref = 'v1'
+prefix = 'stable/'
+"#, remote.display())).expect("Failed to write the config");
+
+    // Prerequisites of source scripts are scripts of the same source.
+    cmd.args(["run", "team/deploy"])
+        .assert()
+        .success()
+        .stdout("source build\ndeploy v1\n");
+    crate::common::cli_cmd(&cfg)
+        .args(["rm", "team/deploy"])
+        .assert()
+        .failure()
+        .stderr(contains("ReadOnlyScript"));
+
+    // Source scripts never end up in the config itself.
+    crate::common::cli_cmd(&cfg)
+        .args(["add", "-a", "other", "echo other"])
+        .assert()
+        .success();
+    let config = std::fs::read_to_string(cfg.path()).expect("Config is readable");
+    assert!(!config.contains("deploy"));
+
+    // Copies of source scripts are part of the config.
+    crate::common::cli_cmd(&cfg)
+        .args(["cp", "team/deploy", "mine"])
+        .assert()
+        .success();
+    let config = std::fs::read_to_string(cfg.path()).expect("Config is readable");
+    assert!(config.contains("[scripts.mine]"));
+    assert!(config.contains("echo deploy v1"));
+
+    std::fs::write(work.join("pier.toml"), "[scripts.deploy]\ncommand = 'echo deploy v2'\n")
+        .expect("Failed to write the source config");
+    crate::common::git(&work, &["commit", "--quiet", "-am", "Update deploy"]);
+    crate::common::git(&work, &["push", "--quiet", "origin", "HEAD"]);
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "team/deploy"])
+        .assert()
+        .success()
+        .stdout("source build\ndeploy v1\n");
+
+    crate::common::cli_cmd(&cfg)
+        .args(["sources", "update"])
+        .assert()
+        .success()
+        .stdout(contains("Updated"));
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "team/deploy"])
+        .assert()
+        .success()
+        .stdout("deploy v2\n");
+    crate::common::cli_cmd(&cfg)
+        .args(["run", "stable/deploy"])
+        .assert()
+        .success()
+        .stdout("source build\ndeploy v1\n");
+
+    // A ref that can't be checked out leaves no clone behind.
+    let sources = root.join("cache/pier/sources");
+    let clones = || std::fs::read_dir(&sources).expect("Sources are cached").count();
+    assert_eq!(clones(), 2);
+    // A broken source is skipped with a warning, the rest of the config still works.
+    cfg.write_str(&format!(
+        "[scripts.local]\ncommand = 'echo local'\n\n[[sources]]\ngit = '{}'\nref = 'missing'\n",
+        remote.display()
+    )).expect("Failed to write the config");
+    crate::common::cli_cmd(&cfg)
+        .args(["list", "-q"])
+        .assert()
+        .success()
+        .stdout("local\n")
+        .stderr(contains("warning: Skipped the scripts of"));
+    assert_eq!(clones(), 2);
+    crate::common::cli_cmd(&cfg)
+        .args(["sources", "update"])
+        .assert()
+        .failure()
+        .stderr(contains("missing"))
+        .stderr(contains("warning").not());
+    assert_eq!(clones(), 2);
+});
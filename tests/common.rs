@@ -33,19 +33,83 @@ pub fn setup_dir(content: &str) -> (ChildPath, TestEnv) {
 }
 
 pub fn setup_cli(content: &str) -> (ChildPath, TestEnv, Command) {
-    let mut cmd = Command::cargo_bin("pier").expect("Failed to set cargo binary pier");
     let (cfg, te) = setup_dir(content);
+    let cmd = cli_cmd(&cfg);
+
+    (cfg, te, cmd)
+}
+
+/// Creates another pier command using the same config file and directories as setup_cli.
+pub fn cli_cmd(cfg: &ChildPath) -> Command {
+    let mut cmd = Command::cargo_bin("pier").expect("Failed to set cargo binary pier");
+    let root = cfg
+        .path()
+        .parent()
+        .expect("Config file has no parent directory");
+
+    cmd.current_dir(root);
+    // Keeps the run history and other state of the tests out of the real state directory.
+    cmd.env("XDG_STATE_HOME", root.join("state"));
+    cmd.env("XDG_CACHE_HOME", root.join("cache"));
+
+    cmd.args(["-c", cfg.path().to_str().unwrap()]);
+
+    cmd
+}
+
+/// Creates a pier command with the args that uses the directory store, run from the test root.
+pub fn store_cmd(te: &TestEnv, store: &ChildPath, args: &[&str]) -> Command {
+    let mut cmd = Command::cargo_bin("pier").expect("Failed to set cargo binary pier");
 
     cmd.current_dir(te.dir.path());
+    cmd.env("XDG_STATE_HOME", te.dir.path().join("state"));
+    cmd.env("XDG_CACHE_HOME", te.dir.path().join("cache"));
 
-    cmd.args(&["-c", cfg.path().to_str().unwrap()]);
+    cmd.args(["-c", store.path().to_str().unwrap()]);
+    cmd.args(args);
 
-    (cfg, te, cmd)
+    cmd
 }
 
 pub fn setup_lib(content: &str) -> (ChildPath, TestEnv, pier::PierResult<Pier>) {
     let (cfg, te) = setup_dir(content);
-    let path = te.dir.path().join(&cfg.path());
+    let path = te.dir.path().join(cfg.path());
     let lib = Pier::from_file(path, false);
     (cfg, te, lib)
 }
+
+/// Sends a raw HTTP request over the stream and returns the whole response.
+pub fn http_request<S: std::io::Read + std::io::Write>(mut stream: S, request: &str) -> String {
+    stream
+        .write_all(request.as_bytes())
+        .expect("Failed to send request");
+
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .expect("Failed to read response");
+
+    response
+}
+
+/// Writes an executable file, creating its directory when needed.
+pub fn write_executable(path: &Path, content: &str) {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::create_dir_all(path.parent().expect("File has no parent directory"))
+        .expect("Failed to create directory");
+    std::fs::write(path, content).expect("Failed to write file");
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+        .expect("Failed to make file executable");
+}
+
+/// Runs git in the directory with an author for the commits, and fails the test when git fails.
+pub fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args(["-c", "user.name=pier", "-c", "user.email=pier@example.com", "-C"])
+        .arg(dir)
+        .args(args)
+        .status()
+        .expect("Failed to run git");
+    assert!(status.success(), "git {} failed", args.join(" "));
+}
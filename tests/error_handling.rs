@@ -1,7 +1,7 @@
 use crate::common::TestEnv;
 use assert_fs::fixture::ChildPath;
 use assert_fs::prelude::*;
-use pier::{error::*, script::Script, Pier};
+use pier::{error::*, schedule::scheduled_scripts, script::Script, usage::SortOrder, Pier, RunOptions};
 
 // Tests that it returns the error AliasNotFound if the alias given does not exist
 pier_test!(lib => test_error_alias_not_found, cfg => r#"
@@ -19,7 +19,7 @@ pier_test!(lib => test_error_no_scripts_exists, cfg => r#""#,
     err_eq!(lib.remove_script(""), NoScriptsExists);
     err_eq!(lib.fetch_script(""), NoScriptsExists);
     err_eq!(lib
-        .list_scripts(None, false, None), NoScriptsExists);
+        .list_scripts(None, SortOrder::Name), NoScriptsExists);
 });
 
 pier_test!(lib => test_error_alias_already_exists, cfg => r#"
@@ -31,8 +31,7 @@ command = 'echo test_1'
     alias: "test_cmd_1".to_string(),
     command: "echo something else".to_string(),
     description: None,
-    reference: None,
-    tags: None
+    ..Script::default()
     };
     err_eq!(lib.add_script(script, false), AliasAlreadyExists);
 });
@@ -60,7 +59,7 @@ pier_test!(basic => test_config_read_error, | te: TestEnv | {
 // Tests that it returns the error ConfigWrite if the file cannot be written to
 // In this case the file is not created
 pier_test!(basic => test_config_write_error, | _te: TestEnv | {
-    let lib = Pier::new();
+    let mut lib = Pier::new();
     err_eq!(lib.write(), ConfigWrite);
 });
 
@@ -78,3 +77,49 @@ pier_test!(basic => test_toml_parse_error, | te: TestEnv| {
     let lib = Pier::from_file(cfg.path().to_path_buf(), false);
     err_eq!(lib, TomlParse);
 });
+
+// Tests that it returns the error DependencyCycle if scripts depend on each other.
+pier_test!(lib => test_error_dependency_cycle, cfg => r#"
+[scripts.build]
+command = 'echo build'
+depends_on = ['deploy']
+
+[scripts.deploy]
+command = 'echo deploy'
+depends_on = ['build']
+"#, | _cfg: ChildPath, lib: Pier | {
+    err_eq!(lib.run_script("deploy", vec![], RunOptions::default()), DependencyCycle);
+    err_eq!(lib.run_script("missing", vec![], RunOptions::default()), AliasNotFound);
+});
+
+// Tests that it returns the error InvalidSchedule if a schedule is not a cron expression
+pier_test!(lib => test_error_invalid_schedule, cfg => r#"
+[scripts.backup]
+command = 'echo backup'
+schedule = 'every night'
+"#, | _cfg: ChildPath, lib: Pier | {
+    err_eq!(scheduled_scripts(&lib), InvalidSchedule);
+});
+
+// Tests that it returns the error AmbiguousAlias if the alias is the last part of more than one
+// alias
+pier_test!(lib => test_error_ambiguous_alias, cfg => r#"
+[scripts.docker.clean]
+command = 'echo docker'
+
+[scripts.cargo.clean]
+command = 'echo cargo'
+"#, | _cfg: ChildPath, mut lib: Pier | {
+    err_eq!(lib.fetch_script("clean"), AmbiguousAlias);
+    err_eq!(lib.remove_script("clean"), AmbiguousAlias);
+});
+
+// Tests that it returns the error EmptyInterpreter if a step has an interpreter without a program
+pier_test!(lib => test_error_empty_interpreter, cfg => r#"
+[scripts.deploy]
+steps = [
+  { command = 'echo deploy', interpreter = [] },
+]
+"#, | _cfg: ChildPath, lib: Pier | {
+    err_eq!(lib.run_script("deploy", vec![], RunOptions::default()), EmptyInterpreter);
+});
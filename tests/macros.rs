@@ -50,9 +50,12 @@ macro_rules! err_eq {
 /// });
 /// ```
 #[macro_export]
+// The tests are only created in this crate, and pass the arguments of commands as borrowed arrays.
+#[allow(clippy::crate_in_macro_def)]
 macro_rules! pier_test {
     (cli => $name:ident, cfg => $content:expr, $func:expr) => {
         #[test]
+        #[allow(clippy::needless_borrows_for_generic_args)]
         fn $name() {
             let (cfg, _dir, cmd) = crate::common::setup_cli(trim!($content));
             $func(cfg, cmd)
@@ -60,6 +63,7 @@ macro_rules! pier_test {
     };
     (lib => $name:ident, cfg => $content:expr, $func:expr) => {
         #[test]
+        #[allow(clippy::needless_borrows_for_generic_args)]
         fn $name() {
             let (cfg, _dir, lib) = crate::common::setup_lib(trim!($content));
             $func(cfg, lib.expect("Failed to load config file."))
@@ -67,6 +71,7 @@ macro_rules! pier_test {
     };
     (basic => $name:ident, $func:expr) => {
         #[test]
+        #[allow(clippy::needless_borrows_for_generic_args)]
         fn $name() {
             let dir = crate::common::TestEnv::new();
             $func(dir)